@@ -0,0 +1,317 @@
+//! Security audit of Surge modules
+//!
+//! Lists what a module can do to a user's traffic: the hostnames it adds to
+//! MITM decryption, the scripts it loads at runtime and from which hosts,
+//! the scripts that read bodies, and the requests it rejects. Comparing the
+//! audits of two versions of a module surfaces new capabilities an upstream
+//! update slipped in.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::module::{Entry, Module, SectionKind};
+
+/// A `[Script]` entry and where its code comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptRef {
+    pub name: String,
+    pub path: String,
+    /// Host of a remote `script-path`; `None` for local paths
+    pub host: Option<String>,
+    pub requires_body: bool,
+}
+
+/// The capabilities of one module
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleAudit {
+    pub mitm_hostnames: Vec<String>,
+    pub scripts: Vec<ScriptRef>,
+    /// `[Rule]` lines with a REJECT policy, typed rules in canonical form
+    pub reject_rules: Vec<String>,
+}
+
+/// What kind of capability an [`AuditChange`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuditItem {
+    MitmHostname,
+    ScriptPath,
+    RequiresBody,
+    RejectRule,
+}
+
+impl AuditItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuditItem::MitmHostname => "MITM hostname",
+            AuditItem::ScriptPath => "script-path",
+            AuditItem::RequiresBody => "requires-body script",
+            AuditItem::RejectRule => "REJECT rule",
+        }
+    }
+}
+
+/// A capability added or removed between two versions of a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditChange {
+    pub item: AuditItem,
+    pub added: bool,
+    pub value: String,
+}
+
+impl fmt::Display for AuditChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.added { "added" } else { "removed" };
+        write!(f, "{} {}: {}", self.item.label(), verb, self.value)
+    }
+}
+
+/// Host of an `http(s)` URL
+fn url_host(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return None;
+    }
+    parsed.host_str().map(str::to_string)
+}
+
+/// Policy field of a `[Rule]` line the rule parser keeps as text, such as
+/// `RULE-SET,<url>,REJECT`: the second field of `FINAL`, else the third,
+/// splitting on commas outside parenthesized groups
+fn other_rule_policy(text: &str) -> Option<&str> {
+    let mut fields = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                fields.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(text[start..].trim());
+    let index = if fields[0].eq_ignore_ascii_case("FINAL") {
+        1
+    } else {
+        2
+    };
+    fields.get(index).copied()
+}
+
+fn is_reject(policy: &str) -> bool {
+    policy.to_ascii_uppercase().starts_with("REJECT")
+}
+
+impl ModuleAudit {
+    pub fn new(module: &Module) -> Self {
+        let scripts = module
+            .scripts()
+            .filter_map(|script| {
+                let path = script.script_path()?;
+                Some(ScriptRef {
+                    name: script.name.clone(),
+                    path: path.to_string(),
+                    host: url_host(path),
+                    requires_body: script.requires_body(),
+                })
+            })
+            .collect();
+        let reject_rules = module
+            .entries(&SectionKind::Rule)
+            .filter_map(|entry| match entry {
+                Entry::Rule(rule) if rule.policy.as_deref().is_some_and(is_reject) => {
+                    Some(rule.to_string())
+                }
+                Entry::Other(text) if other_rule_policy(text).is_some_and(is_reject) => {
+                    Some(text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        Self {
+            mitm_hostnames: module
+                .mitm_hostnames()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            scripts,
+            reject_rules,
+        }
+    }
+
+    /// Every audited value, tagged with its kind
+    fn items(&self) -> BTreeSet<(AuditItem, &str)> {
+        let hostnames = self
+            .mitm_hostnames
+            .iter()
+            .map(|host| (AuditItem::MitmHostname, host.as_str()));
+        let paths = self
+            .scripts
+            .iter()
+            .map(|script| (AuditItem::ScriptPath, script.path.as_str()));
+        let bodies = self
+            .scripts
+            .iter()
+            .filter(|script| script.requires_body)
+            .map(|script| (AuditItem::RequiresBody, script.name.as_str()));
+        let rejects = self
+            .reject_rules
+            .iter()
+            .map(|rule| (AuditItem::RejectRule, rule.as_str()));
+        hostnames
+            .chain(paths)
+            .chain(bodies)
+            .chain(rejects)
+            .collect()
+    }
+
+    /// Capabilities `new` adds or drops compared to this audit
+    pub fn changes(&self, new: &ModuleAudit) -> Vec<AuditChange> {
+        let old_items = self.items();
+        let new_items = new.items();
+        let change = |added: bool| {
+            move |&(item, value): &(AuditItem, &str)| AuditChange {
+                item,
+                added,
+                value: value.to_string(),
+            }
+        };
+        let mut changes: Vec<AuditChange> = new_items
+            .difference(&old_items)
+            .map(change(true))
+            .chain(old_items.difference(&new_items).map(change(false)))
+            .collect();
+        changes.sort_by_key(|change| (change.item, !change.added));
+        changes
+    }
+
+    /// Markdown report for the module `name` synced from `upstream`
+    pub fn to_markdown(&self, name: &str, upstream: &str) -> String {
+        let mut out = format!("# Module audit: {}\n\nUpstream: {}\n\n", name, upstream);
+
+        out.push_str(&format!(
+            "## MITM hostnames ({})\n\n",
+            self.mitm_hostnames.len()
+        ));
+        for host in &self.mitm_hostnames {
+            out.push_str(&format!("- `{}`\n", host));
+        }
+
+        out.push_str(&format!("\n## Scripts ({})\n\n", self.scripts.len()));
+        if !self.scripts.is_empty() {
+            out.push_str("| Script | Host | Requires body | Path |\n| --- | --- | --- | --- |\n");
+            for script in &self.scripts {
+                out.push_str(&format!(
+                    "| {} | {} | {} | `{}` |\n",
+                    script.name.replace('|', "\\|"),
+                    script.host.as_deref().unwrap_or("local"),
+                    if script.requires_body { "yes" } else { "no" },
+                    script.path
+                ));
+            }
+        }
+
+        out.push_str(&format!(
+            "\n## REJECT rules ({})\n\n",
+            self.reject_rules.len()
+        ));
+        for rule in &self.reject_rules {
+            out.push_str(&format!("- `{}`\n", rule));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"#!name=Test
+[Rule]
+DOMAIN,ads.com,REJECT
+DOMAIN,ok.com,DIRECT
+DOMAIN,drop.com,REJECT-DROP,pre-matching
+RULE-SET,https://e.com/ads.list,REJECT
+DOMAIN-SET,https://e.com/pixels.txt,REJECT-TINYGIF
+RULE-SET,https://e.com/reject.list,DIRECT
+
+[Script]
+a = type=http-response,pattern=^https:\/\/a\.com,requires-body=1,script-path=https://raw.githubusercontent.com/u/r/main/a.js
+b = type=http-request,pattern=^https:\/\/b\.com,script-path=b.js
+c = type=cron,cronexp="0 * * * *"
+
+[MITM]
+hostname = %APPEND% a.com, b.com
+"#;
+
+    #[test]
+    fn test_audit_lists_capabilities() {
+        let audit = ModuleAudit::new(&Module::parse(MODULE));
+        assert_eq!(audit.mitm_hostnames, ["a.com", "b.com"]);
+        assert_eq!(
+            audit.scripts,
+            vec![
+                ScriptRef {
+                    name: "a".to_string(),
+                    path: "https://raw.githubusercontent.com/u/r/main/a.js".to_string(),
+                    host: Some("raw.githubusercontent.com".to_string()),
+                    requires_body: true,
+                },
+                ScriptRef {
+                    name: "b".to_string(),
+                    path: "b.js".to_string(),
+                    host: None,
+                    requires_body: false,
+                },
+            ]
+        );
+        assert_eq!(
+            audit.reject_rules,
+            [
+                "DOMAIN,ads.com,REJECT",
+                "DOMAIN,drop.com,REJECT-DROP,pre-matching",
+                "RULE-SET,https://e.com/ads.list,REJECT",
+                "DOMAIN-SET,https://e.com/pixels.txt,REJECT-TINYGIF",
+            ]
+        );
+
+        let markdown = audit.to_markdown("test", "https://e.com/test.sgmodule");
+        assert!(markdown.contains("## MITM hostnames (2)\n\n- `a.com`\n"));
+        assert!(markdown.contains("| a | raw.githubusercontent.com | yes |"));
+        assert!(markdown.contains("| b | local | no | `b.js` |"));
+    }
+
+    #[test]
+    fn test_other_rule_policy() {
+        assert_eq!(
+            other_rule_policy("RULE-SET,https://e.com/a.list,REJECT,no-resolve"),
+            Some("REJECT")
+        );
+        assert_eq!(other_rule_policy("FINAL,REJECT-DROP"), Some("REJECT-DROP"));
+        assert_eq!(other_rule_policy("SUBNET,SSID:Home,DIRECT"), Some("DIRECT"));
+        assert_eq!(other_rule_policy("RULE-SET,SYSTEM"), None);
+    }
+
+    #[test]
+    fn test_changes_between_versions() {
+        let old = ModuleAudit::new(&Module::parse(MODULE));
+        assert!(old.changes(&old).is_empty());
+
+        let updated = MODULE
+            .replace("a.com, b.com", "a.com, c.com")
+            .replace("script-path=b.js", "requires-body=true,script-path=b.js");
+        let new = ModuleAudit::new(&Module::parse(&updated));
+        let changes: Vec<String> = old.changes(&new).iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            changes,
+            [
+                "MITM hostname added: c.com",
+                "MITM hostname removed: b.com",
+                "requires-body script added: b",
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! `surge-sync geoip`
+//!
+//! Downloads the GeoIP MaxMind database (mmdb) from upstream and saves it
+//! locally.
+
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::Result;
+
+use surge_sync::manifest::{Source, SourceKind};
+use surge_sync::report::Status;
+use surge_sync::{gh_annotate, log_status, log_sub, LogLevel, Timer};
+
+use crate::Context;
+
+/// Download the GeoIP database and save it locally
+/// Returns Ok(true) if the file was updated, Ok(false) if skipped (unchanged)
+fn download_geoip(ctx: &Context, source: &Source, geoip_dir: &Path) -> Result<bool> {
+    let filename = format!("{}.mmdb", source.name);
+    log_sub(&format!("Downloading {}", filename));
+
+    ctx.outputs.take();
+    ctx.stats.take();
+    if ctx.unchanged_upstream(source) {
+        log_sub(&format!("{} not modified upstream, skipped", filename));
+        return Ok(false);
+    }
+
+    let data = ctx.download(source, source.url())?;
+    let file_path = geoip_dir.join(&filename);
+
+    // Check if content has actually changed
+    let changed = ctx.write_bytes(&file_path, &data)?;
+    ctx.record_sync(source);
+    if !changed {
+        log_sub(&format!("{} unchanged, skipped", filename));
+        return Ok(false);
+    }
+
+    log_sub(&format!("Saved {} ({} bytes)", filename, data.len()));
+
+    Ok(true)
+}
+
+/// Sync the GeoIP database; a failure here is fatal
+pub fn run(ctx: &Context) -> Result<()> {
+    log_status("Syncing", "GeoIP database from upstream...", LogLevel::Info);
+    let timer = Timer::start("syncing");
+
+    let geoip_dir = ctx.root.join("geoip");
+    ctx.ensure_dir(&geoip_dir)?;
+
+    let Some(source) = ctx.sources(SourceKind::Geoip).into_iter().next() else {
+        log_status("Skipped", "no GeoIP source selected", LogLevel::Info);
+        return Ok(());
+    };
+
+    let started = Instant::now();
+    let result = download_geoip(ctx, source, &geoip_dir);
+    let status = match &result {
+        Ok(true) => Status::Updated,
+        Ok(false) => Status::Unchanged,
+        Err(_) => Status::Failed,
+    };
+    ctx.report_source(source, status, started, result.as_ref().err());
+
+    match result {
+        Ok(changed) => {
+            timer.stop(1);
+            if changed {
+                log_status("Updated", "GeoIP database", LogLevel::Success);
+            } else {
+                log_status("Skipped", "GeoIP database (unchanged)", LogLevel::Info);
+            }
+        }
+        Err(e) => {
+            gh_annotate(
+                "error",
+                &format!("Failed to download GeoIP database: {}", e),
+            );
+            log_status(
+                "Error",
+                &format!("Failed to download GeoIP database: {}", e),
+                LogLevel::Error,
+            );
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! `surge-sync icons`
+//!
+//! Downloads icons from upstream repositories and generates an icons.json
+//! index file.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use serde::{Deserialize, Serialize};
+
+use surge_sync::manifest::{Source, SourceKind};
+use surge_sync::{current_timestamp, log_status, log_sub, LogLevel, Timer};
+
+use crate::{Context, Summary};
+
+/// Icon entry in the JSON index
+#[derive(Serialize, Deserialize, Clone)]
+struct IconEntry {
+    name: String,
+    url: String,
+}
+
+/// Icon index JSON structure
+#[derive(Serialize, Deserialize)]
+struct IconIndex {
+    name: String,
+    description: String,
+    #[serde(rename = "updatedAt")]
+    updated_at: String,
+    icons: Vec<IconEntry>,
+}
+
+/// Download a single icon and save it to the appropriate directory
+/// Returns Ok(true) if the file was updated, Ok(false) if skipped (unchanged)
+fn download_icon(ctx: &Context, source: &Source, icons_dir: &Path) -> Result<bool> {
+    let url = source.url();
+    let category_dir = icons_dir.join(source.category.as_deref().unwrap_or_default());
+    ctx.ensure_dir(&category_dir)?;
+
+    // Get file extension from URL
+    let extension = url.rsplit('.').next().unwrap_or("png");
+
+    let filename = format!("{}.{}", source.name, extension);
+    let file_path = category_dir.join(&filename);
+
+    // Download the icon
+    let data = ctx.download(source, url)?;
+
+    // Only write if content has actually changed
+    ctx.write_bytes(&file_path, &data)
+}
+
+/// Generate the icons.json index file
+/// Returns true if the file was updated, false if unchanged
+fn generate_index(ctx: &Context, icons: &[&Source], icons_dir: &Path) -> Result<bool> {
+    let github_base = "https://raw.githubusercontent.com/hsuyelin/surge-conf/main/icons";
+
+    let entries: Vec<IconEntry> = icons
+        .iter()
+        .map(|source| {
+            // Get the file extension (default to png)
+            let extension = "png";
+            IconEntry {
+                name: source.name.clone(),
+                url: format!(
+                    "{}/{}/{}.{}",
+                    github_base,
+                    source.category.as_deref().unwrap_or_default(),
+                    source.name,
+                    extension
+                ),
+            }
+        })
+        .collect();
+
+    let index = IconIndex {
+        name: "Surge Icons".to_string(),
+        description: "Icons collected from the internet, copyright belongs to original authors"
+            .to_string(),
+        updated_at: current_timestamp(),
+        icons: entries,
+    };
+
+    let json = serde_json::to_string_pretty(&index)?;
+    let json_path = icons_dir.join("icons.json");
+
+    // Compare ignoring the updatedAt field (which contains timestamp)
+    let existing = if json_path.exists() {
+        let existing = fs::read_to_string(&json_path)?;
+        // Filter out the updatedAt line for comparison
+        let filter_updated_at = |s: &str| -> Vec<String> {
+            s.lines()
+                .filter(|line| !line.trim().starts_with("\"updatedAt\""))
+                .map(|l| l.to_string())
+                .collect()
+        };
+        if filter_updated_at(&json) == filter_updated_at(&existing) {
+            return Ok(false);
+        }
+        Some(existing)
+    } else {
+        None
+    };
+
+    ctx.replace_text(&json_path, existing.as_deref(), &json)?;
+    Ok(true)
+}
+
+/// Sync every selected icon source and regenerate the index
+pub fn run(ctx: &Context) -> Result<()> {
+    log_status("Syncing", "icons from upstream...", LogLevel::Info);
+    let timer = Timer::start("syncing");
+
+    let icons_dir = ctx.root.join("icons");
+    ctx.ensure_dir(&icons_dir)?;
+
+    let sources = ctx.sources(SourceKind::Icon);
+    let mut failed: Vec<&str> = Vec::new();
+    let summary = Summary::run(ctx, &sources, |source| {
+        let result = download_icon(ctx, source, &icons_dir);
+        if result.is_err() {
+            failed.push(&source.name);
+        }
+        result
+    });
+
+    // Index every icon in the manifest, including ones filtered out of this
+    // run, but leave out icons that just failed to download
+    let indexed: Vec<&Source> = ctx
+        .manifest
+        .sources_of(SourceKind::Icon)
+        .filter(|source| !failed.contains(&source.name.as_str()))
+        .collect();
+
+    log_sub("Generating icons.json");
+    match generate_index(ctx, &indexed, &icons_dir)? {
+        true => log_sub("icons.json updated"),
+        false => log_sub("icons.json unchanged, skipped"),
+    }
+
+    summary.report("icons", timer);
+
+    Ok(())
+}
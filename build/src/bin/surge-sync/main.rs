@@ -0,0 +1,774 @@
+//! Synchronization tool for Surge configuration
+//!
+//! A single `surge-sync` binary that downloads icons, rules, modules and the
+//! GeoIP database listed in `sources.toml` from their upstream locations.
+
+mod geoip;
+mod icons;
+mod modules;
+mod rules;
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+
+use surge_sync::cache::{sha256_hex, SourceEntry, SyncCache, UrlEntry, CACHE_FILE};
+use surge_sync::changelog::{prepend_entry, Changelog, CHANGELOG_FILE};
+use surge_sync::diff::unified_diff;
+use surge_sync::download::{DownloadError, Downloader, Request, RetryPolicy, DEFAULT_JOBS};
+use surge_sync::guard::GuardError;
+use surge_sync::manifest::{set_pins, Input, Manifest, Source, SourceKind, MANIFEST_FILE};
+use surge_sync::module::ArgumentError;
+use surge_sync::report::{SourceReport, Status, SyncReport};
+use surge_sync::{
+    colors, current_timestamp, ensure_dir, gh_annotate, has_binary_changed, has_text_changed,
+    is_volatile_line, log_status, log_sub, LogLevel, Timer,
+};
+
+/// Exit status of a dry run that found files to change
+const EXIT_WOULD_CHANGE: i32 = 2;
+
+/// Unchanged lines shown around each change in dry-run diffs
+const DIFF_CONTEXT: usize = 3;
+
+/// Diff lines printed per file in a dry run
+const MAX_DIFF_LINES: usize = 200;
+
+/// Sync Surge icons, rules, modules and GeoIP data from upstream
+#[derive(Parser)]
+#[command(name = "surge-sync", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    global: GlobalArgs,
+}
+
+#[derive(Subcommand, Clone, Copy)]
+enum Command {
+    /// Sync rule sets into `rules/`
+    Rules,
+    /// Sync Surge modules into `modules/`
+    Modules,
+    /// Sync icons into `icons/` and regenerate `icons.json`
+    Icons,
+    /// Sync the GeoIP database into `geoip/`
+    Geoip,
+    /// Run every sync in turn
+    All,
+}
+
+/// Flags shared by every subcommand
+#[derive(Args)]
+struct GlobalArgs {
+    /// Project root containing `sources.toml` (defaults to the repository root)
+    #[arg(long, global = true, value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// Only sync sources with this name (may be repeated)
+    #[arg(long, global = true, value_name = "NAME")]
+    only: Vec<String>,
+
+    /// Only sync sources in this category (may be repeated)
+    #[arg(long, global = true, value_name = "CAT")]
+    category: Vec<String>,
+
+    /// Number of downloads to run at once
+    #[arg(short, long, global = true, value_name = "N", default_value_t = DEFAULT_JOBS)]
+    jobs: usize,
+
+    /// Ignore the HTTP cache and download every source in full
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Download and process sources and print a diff of every file that would
+    /// change, without touching the working tree; exits with status 2 if
+    /// anything would change
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Record the SHA-256 of every synced source without a pin in `sources.toml`
+    #[arg(long, global = true)]
+    pin: bool,
+
+    /// Accept content that no longer matches its pin and re-pin the source
+    #[arg(long, global = true)]
+    accept_updates: bool,
+
+    /// Print extra detail for every source
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Write a JSON report of every source's outcome to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    report: Option<PathBuf>,
+}
+
+/// Shared state handed to every subcommand
+pub struct Context {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+    pub only: Vec<String>,
+    pub categories: Vec<String>,
+    pub dry_run: bool,
+    pub verbose: bool,
+    pub no_cache: bool,
+    pub pin: bool,
+    pub accept_updates: bool,
+    pub downloader: Downloader,
+    pub cache: RefCell<SyncCache>,
+    /// Files written or confirmed up to date for the source being synced
+    pub outputs: RefCell<Vec<PathBuf>>,
+    /// New `sha256` pins, keyed by the manifest line of their source
+    pub pins: RefCell<BTreeMap<usize, String>>,
+    /// Measurements of the source being synced
+    pub stats: RefCell<SourceStats>,
+    pub report: RefCell<SyncReport>,
+    /// Rule changes of every set updated in this run
+    pub changelog: RefCell<Changelog>,
+    /// Files a dry run found it would change
+    pub pending: Cell<usize>,
+}
+
+/// What the sync of one source downloaded and produced, for the report
+#[derive(Default)]
+pub struct SourceStats {
+    pub bytes: u64,
+    pub old_entries: Option<usize>,
+    pub new_entries: Option<usize>,
+}
+
+/// Cache key for a source
+fn source_key(source: &Source) -> String {
+    format!("{}/{}", source.kind, source.name)
+}
+
+/// Hash of everything besides upstream content that shapes a source's
+/// output, so a changed definition or tool version forces a full sync
+fn fingerprint(source: &Source) -> String {
+    let definition = format!(
+        "{} {:?} {:?} {:?} {:?} {:?} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        source.category,
+        source.inputs,
+        source.sha256,
+        source.exclude,
+        source.patch,
+        source.options
+    );
+    sha256_hex(definition.as_bytes())
+}
+
+impl Context {
+    /// Enabled sources of `kind` that pass the `--only` and `--category` filters
+    pub fn sources(&self, kind: SourceKind) -> Vec<&Source> {
+        self.manifest
+            .sources_of(kind)
+            .filter(|source| self.only.is_empty() || self.only.contains(&source.name))
+            .filter(|source| {
+                self.categories.is_empty()
+                    || source
+                        .category
+                        .as_ref()
+                        .is_some_and(|c| self.categories.contains(c))
+            })
+            .collect()
+    }
+
+    /// Download request for the remote input `url` of `source`, with its
+    /// mirrors, retry settings and pin. The pin is left out with
+    /// `--accept-updates`, which checks it in [`Context::check_pin`] instead.
+    pub fn request(&self, source: &Source, url: &str) -> Request {
+        let pinned = url == source.url() && !self.accept_updates;
+        Request {
+            url: url.to_string(),
+            mirrors: source.mirrors_for(url),
+            retry: RetryPolicy::new(source.options.retries),
+            sha256: source.sha256.clone().filter(|_| pinned),
+        }
+    }
+
+    /// Download the remote input `url` of `source`
+    pub fn download(&self, source: &Source, url: &str) -> Result<Vec<u8>, DownloadError> {
+        let bytes = self.downloader.get(&self.request(source, url))?;
+        self.stats.borrow_mut().bytes += bytes.len() as u64;
+        self.check_pin(source, url, &bytes);
+        Ok(bytes)
+    }
+
+    /// Download UTF-8 text for the remote input `url` of `source`
+    pub fn download_text(&self, source: &Source, url: &str) -> Result<String, DownloadError> {
+        let text = self.downloader.get_text(&self.request(source, url))?;
+        self.stats.borrow_mut().bytes += text.len() as u64;
+        self.check_pin(source, url, text.as_bytes());
+        Ok(text)
+    }
+
+    /// Queue a new pin for `source` when `--pin` finds it unpinned or
+    /// `--accept-updates` finds its content changed
+    fn check_pin(&self, source: &Source, url: &str, content: &[u8]) {
+        if url != source.url() {
+            return;
+        }
+        let actual = sha256_hex(content);
+        let message = match &source.sha256 {
+            None if self.pin => format!("{} pinned to sha256 {}", source.name, actual),
+            Some(expected) if self.accept_updates && *expected != actual => format!(
+                "{} changed upstream, re-pinned from sha256 {} to {}",
+                source.name, expected, actual
+            ),
+            _ => return,
+        };
+        log_sub(&message);
+        self.pins.borrow_mut().insert(source.line, actual);
+    }
+
+    /// Mirror that served `url` in this run, if it was not `url` itself
+    pub fn mirror_for(&self, url: &str) -> Option<String> {
+        self.downloader
+            .served_by(url)
+            .filter(|served_by| served_by != url)
+    }
+
+    /// True if the last sync of `source` is still current: its definition is
+    /// unchanged, its outputs exist and every input answered a conditional
+    /// request with `304 Not Modified`
+    pub fn unchanged_upstream(&self, source: &Source) -> bool {
+        // Pinning needs the content of every unpinned source, and vendored
+        // scripts are not cached, so a 304 for the module says nothing of them
+        if self.no_cache || (self.pin && source.sha256.is_none()) || source.options.vendor_scripts {
+            return false;
+        }
+        let cache = self.cache.borrow();
+        let Some(entry) = cache.sources.get(&source_key(source)) else {
+            return false;
+        };
+        if entry.fingerprint != fingerprint(source)
+            || !entry
+                .outputs
+                .iter()
+                .all(|path| self.root.join(path).exists())
+        {
+            return false;
+        }
+
+        source.inputs.iter().all(|input| match input {
+            Input::Remote(url) => {
+                cache.urls.get(url).is_some_and(UrlEntry::has_validators)
+                    && self.downloader.not_modified(&self.request(source, url))
+            }
+            Input::Local(_) => false,
+        })
+    }
+
+    /// Remember what a successful sync of `source` downloaded and produced
+    pub fn record_sync(&self, source: &Source) {
+        let outputs = self.outputs.take();
+        if self.dry_run {
+            return;
+        }
+        let mut cache = self.cache.borrow_mut();
+        for input in &source.inputs {
+            if let Input::Remote(url) = input {
+                if let Some(entry) = self.downloader.received(url) {
+                    cache.urls.insert(url.clone(), entry);
+                }
+            }
+        }
+        cache.sources.insert(
+            source_key(source),
+            SourceEntry {
+                fingerprint: fingerprint(source),
+                outputs,
+            },
+        );
+    }
+
+    /// Add the outcome of the source being synced to the report
+    pub fn report_source(
+        &self,
+        source: &Source,
+        status: Status,
+        started: Instant,
+        error: Option<&anyhow::Error>,
+    ) {
+        let stats = self.stats.take();
+        self.report.borrow_mut().push(SourceReport {
+            kind: source.kind.to_string(),
+            name: source.name.clone(),
+            status,
+            upstreams: source.inputs.iter().map(Input::to_string).collect(),
+            old_entries: stats.old_entries,
+            new_entries: stats.new_entries,
+            bytes: stats.bytes,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error_kind: error.map(failure_reason),
+            error: error.map(|e| format!("{:#}", e)),
+        });
+    }
+
+    /// Note `path` as an output of the source being synced
+    fn track_output(&self, path: &Path) {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.outputs.borrow_mut().push(relative.to_path_buf());
+    }
+
+    /// Print a sub-item only when `--verbose` is set
+    pub fn detail(&self, message: &str) {
+        if self.verbose {
+            log_sub(message);
+        }
+    }
+
+    /// Create a directory for outputs; nothing is created in dry-run mode
+    pub fn ensure_dir(&self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        ensure_dir(path)
+    }
+
+    /// Path relative to the project root, for display
+    fn relative<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    /// Write text to `path` unless it only differs in its timestamp.
+    /// Returns true if the content changed; nothing is written in dry-run mode.
+    pub fn write_text(&self, path: &Path, content: &str) -> Result<bool> {
+        self.track_output(path);
+        let existing = if path.exists() {
+            let existing = fs::read_to_string(path)?;
+            if !has_text_changed(content, &existing) {
+                return Ok(false);
+            }
+            Some(existing)
+        } else {
+            None
+        };
+        self.replace_text(path, existing.as_deref(), content)?;
+        Ok(true)
+    }
+
+    /// Write changed text to `path`, or in dry-run mode print its diff
+    /// against the `existing` content instead
+    pub fn replace_text(&self, path: &Path, existing: Option<&str>, content: &str) -> Result<()> {
+        if !self.dry_run {
+            fs::write(path, content)?;
+            return Ok(());
+        }
+        self.pending.set(self.pending.get() + 1);
+
+        let relative = self.relative(path);
+        let (old_label, old) = match existing {
+            Some(existing) => (format!("a/{}", relative.display()), existing),
+            None => ("/dev/null".to_string(), ""),
+        };
+        println!("{}--- {}{}", colors::BOLD, old_label, colors::RESET);
+        println!(
+            "{}+++ b/{}{}",
+            colors::BOLD,
+            relative.display(),
+            colors::RESET
+        );
+        let diff = unified_diff(old, content, is_volatile_line, DIFF_CONTEXT);
+        let total = diff.lines().count();
+        for line in diff.lines().take(MAX_DIFF_LINES) {
+            let color = match line.as_bytes().first() {
+                Some(b'+') => colors::GREEN,
+                Some(b'-') => colors::RED,
+                Some(b'@') => colors::CYAN,
+                _ => "",
+            };
+            println!("{}{}{}", color, line, colors::RESET);
+        }
+        if total > MAX_DIFF_LINES {
+            println!("... {} more diff lines", total - MAX_DIFF_LINES);
+        }
+        Ok(())
+    }
+
+    /// Delete a file a sync no longer produces; in dry-run mode only report it
+    pub fn remove_file(&self, path: &Path) -> Result<()> {
+        if !self.dry_run {
+            fs::remove_file(path)?;
+            return Ok(());
+        }
+        self.pending.set(self.pending.get() + 1);
+        println!(
+            "{}Deleted file {}{}",
+            colors::BOLD,
+            self.relative(path).display(),
+            colors::RESET
+        );
+        Ok(())
+    }
+
+    /// Write binary data to `path` if it differs from what is on disk.
+    /// Returns true if the content changed; nothing is written in dry-run mode.
+    pub fn write_bytes(&self, path: &Path, data: &[u8]) -> Result<bool> {
+        self.track_output(path);
+        if !has_binary_changed(data, path) {
+            return Ok(false);
+        }
+        if self.dry_run {
+            self.pending.set(self.pending.get() + 1);
+            let old_size = fs::metadata(path).map_or(0, |m| m.len());
+            println!(
+                "{}Binary file {} differs ({} -> {} bytes){}",
+                colors::BOLD,
+                self.relative(path).display(),
+                old_size,
+                data.len(),
+                colors::RESET
+            );
+        } else {
+            fs::write(path, data)?;
+        }
+        Ok(true)
+    }
+}
+
+/// Per-run success/updated/failed counters
+#[derive(Default)]
+pub struct Summary {
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    /// Failures per reason, e.g. `timeout` or `HTTP 404`
+    pub reasons: BTreeMap<String, usize>,
+}
+
+/// Short label for why a source failed
+pub fn failure_reason(error: &anyhow::Error) -> String {
+    if let Some(error) = error.downcast_ref::<DownloadError>() {
+        return error.reason();
+    }
+    if error.downcast_ref::<GuardError>().is_some() {
+        return "guard".to_string();
+    }
+    if error.downcast_ref::<ArgumentError>().is_some() {
+        return "arguments".to_string();
+    }
+    "processing".to_string()
+}
+
+impl Summary {
+    /// Sync each source in order, logging progress and counting the outcome
+    pub fn run<'a, F>(ctx: &Context, sources: &[&'a Source], mut sync: F) -> Self
+    where
+        F: FnMut(&'a Source) -> Result<bool>,
+    {
+        // Download every remote input up front; sources are then processed
+        // and logged in manifest order whatever order the downloads finish in
+        let requests: Vec<Request> = sources
+            .iter()
+            .flat_map(|source| {
+                source.inputs.iter().filter_map(move |input| match input {
+                    Input::Remote(url) => Some(ctx.request(source, url)),
+                    Input::Local(_) => None,
+                })
+            })
+            .collect();
+        if !requests.is_empty() {
+            ctx.detail(&format!(
+                "fetching {} files with {} workers",
+                requests.len(),
+                ctx.downloader.jobs().min(requests.len())
+            ));
+            ctx.downloader.prefetch(&requests);
+        }
+
+        let mut summary = Summary::default();
+        for source in sources {
+            log_sub(&format!("Downloading {}", source.name));
+            for input in &source.inputs {
+                ctx.detail(&format!("from {}", input));
+            }
+
+            let started = Instant::now();
+            ctx.outputs.take();
+            ctx.stats.take();
+            if ctx.unchanged_upstream(source) {
+                summary.unchanged += 1;
+                log_sub(&format!("{} not modified upstream, skipped", source.name));
+                ctx.report_source(source, Status::Unchanged, started, None);
+                continue;
+            }
+
+            let result = sync(source);
+            if result.is_ok() {
+                ctx.record_sync(source);
+            }
+            let status = match &result {
+                Ok(true) => Status::Updated,
+                Ok(false) => Status::Unchanged,
+                Err(_) => Status::Failed,
+            };
+            ctx.report_source(source, status, started, result.as_ref().err());
+            match result {
+                Ok(true) => {
+                    summary.updated += 1;
+                    let verb = if ctx.dry_run {
+                        "would be updated"
+                    } else {
+                        "updated"
+                    };
+                    log_sub(&format!("{} {}", source.name, verb));
+                }
+                Ok(false) => {
+                    summary.unchanged += 1;
+                    log_sub(&format!("{} unchanged, skipped", source.name));
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    let reason = failure_reason(&e);
+                    // Content that no longer matches its pin is kept out
+                    // and must be accepted explicitly
+                    let level = match e.downcast_ref::<DownloadError>() {
+                        Some(DownloadError::Checksum { .. }) => "error",
+                        _ => "warning",
+                    };
+                    gh_annotate(
+                        level,
+                        &format!("Failed to sync {} ({}): {:#}", source.name, reason, e),
+                    );
+                    *summary.reasons.entry(reason).or_insert(0) += 1;
+                    // Continue with other sources - skip failed ones
+                }
+            }
+        }
+        summary
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.updated + self.unchanged
+    }
+
+    /// Print the summary line and a warning if anything failed
+    pub fn report(&self, noun: &str, timer: Timer) {
+        timer.stop(self.succeeded());
+
+        log_status(
+            "Summary",
+            &format!(
+                "{} updated, {} unchanged, {} failed",
+                self.updated, self.unchanged, self.failed
+            ),
+            LogLevel::Info,
+        );
+
+        if self.failed > 0 {
+            log_status(
+                "Warning",
+                &format!(
+                    "{} {} failed to sync ({})",
+                    self.failed,
+                    noun,
+                    self.reasons
+                        .iter()
+                        .map(|(reason, count)| format!("{}: {}", reason, count))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                LogLevel::Warning,
+            );
+        }
+    }
+}
+
+/// Get the project root directory
+fn get_project_root(explicit: Option<PathBuf>) -> PathBuf {
+    if let Some(root) = explicit {
+        return root;
+    }
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::current_dir().unwrap());
+
+    // If running from build directory, go up one level
+    if manifest_dir.ends_with("build") {
+        manifest_dir.parent().unwrap().to_path_buf()
+    } else {
+        manifest_dir
+    }
+}
+
+/// Write the pins recorded during this run into `sources.toml`
+fn save_pins(ctx: &Context) -> Result<()> {
+    let pins = ctx.pins.borrow();
+    if pins.is_empty() {
+        return Ok(());
+    }
+    let verb = if ctx.dry_run { "would pin" } else { "pinned" };
+    log_status(
+        "Pins",
+        &format!("{} {} sources in {}", verb, pins.len(), MANIFEST_FILE),
+        LogLevel::Info,
+    );
+    if !ctx.dry_run {
+        let path = ctx.root.join(MANIFEST_FILE);
+        let text = fs::read_to_string(&path)?;
+        fs::write(&path, set_pins(&text, &pins))?;
+    }
+    Ok(())
+}
+
+/// Append this run's rule changes to the GitHub Actions step summary when
+/// there is one, or add them as a new entry at the top of `CHANGELOG-rules.md`
+fn save_changelog(ctx: &Context) -> Result<()> {
+    let changelog = ctx.changelog.borrow();
+    if changelog.is_empty() {
+        return Ok(());
+    }
+    if ctx.dry_run {
+        // Summarize the semantic diff of each set instead of recording it
+        log_status("Changelog", "rule changes per set", LogLevel::Info);
+        for set in &changelog.sets {
+            let types: Vec<String> = set
+                .diff
+                .counts_by_type()
+                .iter()
+                .map(|(rule_type, (added, removed))| {
+                    format!("{} +{} -{}", rule_type, added, removed)
+                })
+                .collect();
+            let warning = if set.is_large_removal() {
+                ", large removal"
+            } else {
+                ""
+            };
+            log_sub(&format!(
+                "{}: +{} -{} rules ({}{})",
+                set.name,
+                set.diff.added.len(),
+                set.diff.removed.len(),
+                types.join(", "),
+                warning
+            ));
+        }
+        return Ok(());
+    }
+    let entry = changelog.to_markdown(&current_timestamp());
+
+    let step_summary = std::env::var_os("GITHUB_STEP_SUMMARY").filter(|path| !path.is_empty());
+    let path = match step_summary {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?
+                .write_all(entry.as_bytes())?;
+            path
+        }
+        None => {
+            let path = ctx.root.join(CHANGELOG_FILE);
+            let existing = fs::read_to_string(&path).unwrap_or_default();
+            fs::write(&path, prepend_entry(&existing, &entry))?;
+            path
+        }
+    };
+    log_status(
+        "Changelog",
+        &format!(
+            "{} rule sets changed, see {}",
+            changelog.sets.len(),
+            path.display()
+        ),
+        LogLevel::Info,
+    );
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let started = Instant::now();
+    let cli = Cli::parse();
+    let root = get_project_root(cli.global.root);
+    let manifest = Manifest::load(&root.join(MANIFEST_FILE))?;
+
+    let cache_path = root.join(CACHE_FILE);
+    let cache = SyncCache::load(&cache_path).unwrap_or_else(|e| {
+        gh_annotate("warning", &format!("Ignoring unreadable cache {}", e));
+        SyncCache::default()
+    });
+    let mut http = manifest.http.clone();
+    for path in &mut http.ca_certs {
+        *path = root.join(&path);
+    }
+    http.apply_env()?;
+    let mut downloader = Downloader::new(cli.global.jobs, &http)?;
+    if !cli.global.no_cache {
+        downloader = downloader.with_validators(cache.urls.clone().into_iter().collect());
+    }
+
+    let ctx = Context {
+        root,
+        manifest,
+        only: cli.global.only,
+        categories: cli.global.category,
+        dry_run: cli.global.dry_run,
+        verbose: cli.global.verbose,
+        no_cache: cli.global.no_cache,
+        pin: cli.global.pin,
+        accept_updates: cli.global.accept_updates,
+        downloader,
+        cache: RefCell::new(cache),
+        outputs: RefCell::new(Vec::new()),
+        pins: RefCell::new(BTreeMap::new()),
+        stats: RefCell::new(SourceStats::default()),
+        report: RefCell::new(SyncReport::new(cli.global.dry_run)),
+        changelog: RefCell::new(Changelog::default()),
+        pending: Cell::new(0),
+    };
+
+    if ctx.dry_run {
+        log_status("Dry run", "no files will be written", LogLevel::Warning);
+    }
+
+    let result = match cli.command {
+        Command::Rules => rules::run(&ctx),
+        Command::Modules => modules::run(&ctx),
+        Command::Icons => icons::run(&ctx),
+        Command::Geoip => geoip::run(&ctx),
+        Command::All => icons::run(&ctx)
+            .and_then(|_| rules::run(&ctx))
+            .and_then(|_| modules::run(&ctx))
+            .and_then(|_| geoip::run(&ctx)),
+    };
+
+    // Keep what was learned from the sources that did sync
+    if !ctx.dry_run {
+        ctx.cache.borrow().save(&cache_path)?;
+    }
+    save_pins(&ctx)?;
+    save_changelog(&ctx)?;
+    if let Some(path) = &cli.global.report {
+        let mut report = ctx.report.borrow_mut();
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        fs::write(path, report.to_json())?;
+    }
+    result?;
+
+    if ctx.dry_run {
+        let pending = ctx.pending.get();
+        if pending > 0 {
+            log_status(
+                "Dry run",
+                &format!("{} files would change", pending),
+                LogLevel::Warning,
+            );
+            std::process::exit(EXIT_WOULD_CHANGE);
+        }
+        log_status("Dry run", "nothing would change", LogLevel::Success);
+    }
+    Ok(())
+}
@@ -0,0 +1,295 @@
+//! `surge-sync modules`
+//!
+//! Downloads Surge modules from upstream repositories, applies the patches
+//! and argument overrides of their sources, and writes an audit of what each
+//! one can do next to it. Modules may have their remote scripts vendored
+//! under `modules/scripts/`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+
+use surge_sync::audit::ModuleAudit;
+use surge_sync::manifest::{ArgumentMode, Source, SourceKind};
+use surge_sync::module::{Entry, Line, Module, SectionKind};
+use surge_sync::vendor::{restore_upstream_paths, script_file_name, vendored_url};
+use surge_sync::{current_timestamp, gh_annotate, log_status, LogLevel, Timer};
+
+use crate::{Context, Summary};
+
+/// Generate a standardized header for a module file, naming the mirror
+/// that served it when the upstream URL failed and noting how the upstream
+/// content was changed
+fn generate_header(
+    name: &str,
+    upstream_url: &str,
+    served_by: Option<&str>,
+    notes: &[String],
+) -> String {
+    let mut extra_lines: String = served_by
+        .map(|mirror| format!("# Served By: {}\n", mirror))
+        .unwrap_or_default();
+    for note in notes {
+        extra_lines.push_str(&format!("# {}\n", note));
+    }
+    format!(
+        r#"#########################################
+# {}
+# Last Updated: {}
+# Upstream: {}
+{}# GitHub: https://github.com/hsuyelin/surge-conf
+#########################################
+"#,
+        name,
+        current_timestamp(),
+        upstream_url,
+        extra_lines
+    )
+}
+
+/// Apply the source's patch to `module`. Returns a header note summarizing
+/// it, or `None` if the source has no patch.
+fn apply_patch(source: &Source, module: &mut Module) -> Option<String> {
+    let patch = source.patch.as_ref()?;
+    let removed = module.apply_patch(patch);
+
+    let mut changes = Vec::new();
+    if patch.name.is_some() {
+        changes.push("#!name".to_string());
+    }
+    if patch.desc.is_some() {
+        changes.push("#!desc".to_string());
+    }
+    for ((kind, pattern), count) in patch.remove.iter().zip(removed) {
+        if count == 0 {
+            // The upstream may have dropped or renamed what the patch targets
+            gh_annotate(
+                "warning",
+                &format!(
+                    "Patch pattern `{}` of module {} matched nothing in [{}]",
+                    pattern,
+                    source.name,
+                    kind.name()
+                ),
+            );
+        }
+        changes.push(format!("-{} [{}]", count, kind.name()));
+    }
+    for (kind, lines) in &patch.append {
+        changes.push(format!("+{} [{}]", lines.len(), kind.name()));
+    }
+    Some(format!("Patched: {}", changes.join(", ")))
+}
+
+/// Apply the source's argument overrides to `module`. Returns a header note
+/// describing them, or `None` if the module was left as is.
+fn apply_arguments(source: &Source, module: &mut Module) -> Result<Option<String>> {
+    let options = &source.options;
+    match options.arguments_mode {
+        ArgumentMode::Defaults if options.arguments.is_empty() => return Ok(None),
+        ArgumentMode::Defaults => module.set_argument_defaults(&options.arguments)?,
+        ArgumentMode::Render => *module = module.render_arguments(&options.arguments)?,
+    }
+    let values = if options.arguments.is_empty() {
+        "module defaults".to_string()
+    } else {
+        options
+            .arguments
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Ok(Some(format!(
+        "Arguments ({}): {}",
+        options.arguments_mode.as_str(),
+        values
+    )))
+}
+
+/// Download every remote `script-path` of `module` into
+/// `modules/scripts/<name>/` and point the module at the vendored copies.
+/// Returns true if any script file changed.
+fn vendor_scripts(
+    ctx: &Context,
+    source: &Source,
+    module: &mut Module,
+    scripts_dir: &Path,
+) -> Result<bool> {
+    let base_url = ctx
+        .manifest
+        .raw_base_url
+        .as_deref()
+        .context("[modules] has no raw_base_url")?;
+    let mut files: HashMap<String, String> = HashMap::new();
+    let mut names: HashSet<String> = HashSet::new();
+    let mut changed = false;
+
+    for section in &mut module.sections {
+        if section.kind != SectionKind::Script {
+            continue;
+        }
+        for line in &mut section.lines {
+            let Some(Entry::Script(script)) = line.entry() else {
+                continue;
+            };
+            let Some(url) = script
+                .script_path()
+                .filter(|path| path.starts_with("https://") || path.starts_with("http://"))
+                .map(str::to_string)
+            else {
+                continue;
+            };
+
+            if !files.contains_key(&url) {
+                let file = script_file_name(&url, &names);
+                let content = ctx.download_text(source, &url)?;
+                let served_by = ctx.mirror_for(&url);
+                let header = generate_header(
+                    &format!("{}/{}", source.name, file),
+                    &url,
+                    served_by.as_deref(),
+                    &[],
+                );
+                ctx.ensure_dir(scripts_dir)?;
+                // Scripts are JavaScript, so the header goes in a block comment
+                changed |= ctx.write_text(
+                    &scripts_dir.join(&file),
+                    &format!("/*\n{}*/\n{}", header, content),
+                )?;
+                names.insert(file.clone());
+                files.insert(url.clone(), file);
+            }
+
+            let mut script = script.clone();
+            script.set(
+                "script-path",
+                &vendored_url(base_url, &source.name, &files[&url]),
+            );
+            *line = Line::new(Entry::Script(script));
+        }
+    }
+
+    if !files.is_empty() {
+        ctx.detail(&format!("vendored {} scripts", files.len()));
+    }
+    Ok(remove_stale_scripts(ctx, scripts_dir, &names)? || changed)
+}
+
+/// Delete the files in `scripts_dir` other than `keep`, left over from
+/// scripts a module no longer loads, and the directory once it is empty.
+/// Returns true if anything was deleted.
+fn remove_stale_scripts(ctx: &Context, scripts_dir: &Path, keep: &HashSet<String>) -> Result<bool> {
+    let Ok(dir) = fs::read_dir(scripts_dir) else {
+        return Ok(false);
+    };
+    let mut removed = false;
+    for entry in dir {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_file() && !keep.contains(name.to_string_lossy().as_ref()) {
+            ctx.detail(&format!("removed stale script {}", name.to_string_lossy()));
+            ctx.remove_file(&entry.path())?;
+            removed = true;
+        }
+    }
+    if keep.is_empty() && !ctx.dry_run {
+        // Fails harmlessly if anything but stale scripts is left
+        fs::remove_dir(scripts_dir).ok();
+    }
+    Ok(removed)
+}
+
+/// Flag capabilities the new version of a module adds or drops compared to
+/// the file on disk. A first sync has nothing to compare against.
+fn check_audit(
+    ctx: &Context,
+    source: &Source,
+    file_path: &Path,
+    modules_dir: &Path,
+    audit: &ModuleAudit,
+) {
+    let Ok(existing) = fs::read_to_string(file_path) else {
+        return;
+    };
+    let mut existing = Module::parse(&existing);
+    if let Some(base_url) = &ctx.manifest.raw_base_url {
+        restore_upstream_paths(&mut existing, base_url, modules_dir);
+    }
+    for change in ModuleAudit::new(&existing).changes(audit) {
+        log_status(
+            "Audit",
+            &format!("{}: {}", source.name, change),
+            LogLevel::Warning,
+        );
+        gh_annotate(
+            "error",
+            &format!(
+                "Module {} changed its capabilities: {}",
+                source.name, change
+            ),
+        );
+    }
+}
+
+/// Download and process a single module file
+/// Returns Ok(true) if the file was updated, Ok(false) if skipped (unchanged)
+fn sync_module(ctx: &Context, source: &Source, modules_dir: &Path) -> Result<bool> {
+    let category_dir = modules_dir.join(source.category.as_deref().unwrap_or_default());
+    ctx.ensure_dir(&category_dir)?;
+
+    let filename = format!("{}.sgmodule", source.name);
+    let file_path = category_dir.join(&filename);
+
+    // Download content
+    let content = ctx.download_text(source, source.url())?;
+
+    let mut module = Module::parse(&content);
+    let mut notes = Vec::new();
+    notes.extend(apply_patch(source, &mut module));
+    notes.extend(apply_arguments(source, &mut module)?);
+
+    let audit = ModuleAudit::new(&module);
+    check_audit(ctx, source, &file_path, modules_dir, &audit);
+
+    let scripts_dir = modules_dir.join("scripts").join(&source.name);
+    let scripts_changed = if source.options.vendor_scripts {
+        vendor_scripts(ctx, source, &mut module, &scripts_dir)?
+    } else {
+        remove_stale_scripts(ctx, &scripts_dir, &HashSet::new())?
+    };
+
+    // Generate new header
+    let served_by = ctx.mirror_for(source.url());
+    let header = generate_header(&source.name, source.url(), served_by.as_deref(), &notes);
+
+    // Write file with new header + module content, unchanged unless the
+    // source rewrites it
+    let final_content = format!("{}\n{}", header, module.to_text());
+
+    // Only write if content has actually changed (ignoring timestamp)
+    let module_changed = ctx.write_text(&file_path, &final_content)?;
+    let audit_path = category_dir.join(format!("{}.audit.md", source.name));
+    let audit_changed =
+        ctx.write_text(&audit_path, &audit.to_markdown(&source.name, source.url()))?;
+    Ok(module_changed || audit_changed || scripts_changed)
+}
+
+/// Sync every selected module source
+pub fn run(ctx: &Context) -> Result<()> {
+    log_status("Syncing", "modules from upstream...", LogLevel::Info);
+    let timer = Timer::start("syncing");
+
+    let modules_dir = ctx.root.join("modules");
+    ctx.ensure_dir(&modules_dir)?;
+
+    let sources = ctx.sources(SourceKind::Module);
+    let summary = Summary::run(ctx, &sources, |source| {
+        sync_module(ctx, source, &modules_dir)
+    });
+    summary.report("modules", timer);
+
+    Ok(())
+}
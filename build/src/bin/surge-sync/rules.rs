@@ -0,0 +1,432 @@
+//! `surge-sync rules`
+//!
+//! Downloads rule sets from upstream repositories, optionally merging several
+//! inputs into one composite set, and organizes them into categorized
+//! directories with proper headers. Sets can also be exported to other
+//! clients' formats.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+
+use surge_sync::changelog::RuleDiff;
+use surge_sync::export::{self, clash, loon, quantumultx, singbox};
+use surge_sync::guard::{check_counts, check_sentinel};
+use surge_sync::import;
+use surge_sync::manifest::{
+    ExportTarget, Input, InputFormat, InvalidLinePolicy, Source, SourceKind,
+};
+use surge_sync::optimize::{dedupe, exclude, optimize};
+use surge_sync::rule::{format_rules, is_comment_or_blank, RuleEntry, RuleSet};
+use surge_sync::{current_timestamp, gh_annotate, log_status, LogLevel, Timer};
+
+use crate::{Context, Summary};
+
+/// Rules left out of each export target during a run, counted per rule type
+type UnsupportedCounts = BTreeMap<ExportTarget, BTreeMap<&'static str, usize>>;
+
+/// Format the entry count alongside the count before optimization
+fn format_entries(raw_count: usize, entry_count: usize) -> String {
+    format!("{} (raw: {})", entry_count, raw_count)
+}
+
+/// Generate a standardized header for a rule file, with one `Upstream`
+/// line per input that contributed to it, each followed by the mirror that
+/// served it when the primary URL failed
+fn generate_header(
+    ctx: &Context,
+    name: &str,
+    upstreams: &[Input],
+    raw_count: usize,
+    entry_count: usize,
+) -> String {
+    let upstream_lines: String = upstreams
+        .iter()
+        .map(|input| {
+            let mirror = match input {
+                Input::Remote(url) => ctx.mirror_for(url),
+                Input::Local(_) => None,
+            };
+            match mirror {
+                Some(mirror) => format!("# Upstream: {}\n# Served By: {}\n", input, mirror),
+                None => format!("# Upstream: {}\n", input),
+            }
+        })
+        .collect();
+    format!(
+        r#"#########################################
+# {}
+# Last Updated: {}
+# Entries: {}
+{}# GitHub: https://github.com/hsuyelin/surge-conf
+#########################################
+"#,
+        name,
+        current_timestamp(),
+        format_entries(raw_count, entry_count),
+        upstream_lines
+    )
+}
+
+/// Strip existing header comments and return clean content with original rules
+fn strip_header(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut start_idx = 0;
+
+    // Skip leading comment blocks
+    for (i, line) in lines.iter().enumerate() {
+        if is_comment_or_blank(line) {
+            start_idx = i + 1;
+        } else {
+            break;
+        }
+    }
+
+    // Find where actual rules start (skip blank lines after header)
+    while start_idx < lines.len() && lines[start_idx].trim().is_empty() {
+        start_idx += 1;
+    }
+
+    lines[start_idx..].join("\n")
+}
+
+/// Rules of the file already at `path`, if there is one
+fn existing_entries(path: &Path) -> Option<Vec<RuleEntry>> {
+    let content = fs::read_to_string(path).ok()?;
+    Some(RuleSet::parse(&strip_header(&content)).entries)
+}
+
+/// Drop or reject the lines Surge would refuse to load, per the source's
+/// `on_invalid` policy. Returns the content to write.
+fn apply_invalid_policy(
+    ctx: &Context,
+    source: &Source,
+    content: &str,
+    rule_set: &RuleSet,
+) -> Result<String> {
+    let Some(first) = rule_set.diagnostics.first() else {
+        return Ok(content.to_string());
+    };
+
+    match source.options.on_invalid {
+        InvalidLinePolicy::Fail => anyhow::bail!(
+            "{} lines Surge would reject, first at {}",
+            rule_set.diagnostics.len(),
+            first
+        ),
+        InvalidLinePolicy::Drop => {
+            // One annotation per source; the lines themselves are verbose output
+            for diagnostic in &rule_set.diagnostics {
+                ctx.detail(&format!("dropped {}", diagnostic));
+            }
+            gh_annotate(
+                "warning",
+                &format!(
+                    "{}: dropped {} lines Surge would reject, first at {}",
+                    source.name,
+                    rule_set.diagnostics.len(),
+                    first
+                ),
+            );
+            let dropped: HashSet<usize> = rule_set.diagnostics.iter().map(|d| d.line).collect();
+            let kept: Vec<&str> = content
+                .lines()
+                .enumerate()
+                .filter(|(i, _)| !dropped.contains(&(i + 1)))
+                .map(|(_, line)| line)
+                .collect();
+            Ok(kept.join("\n"))
+        }
+    }
+}
+
+/// Convert a non-Surge list to Surge rules, reporting the lines that have no
+/// Surge equivalent
+fn convert_input(ctx: &Context, source: &Source, input: &Input, content: String) -> String {
+    let format = source.options.format;
+    if format == InputFormat::Surge {
+        return content;
+    }
+
+    let conversion = import::convert(format, &content);
+    if !conversion.skipped.is_empty() {
+        for skipped in &conversion.skipped {
+            ctx.detail(&format!("not converted: {}", skipped));
+        }
+        let mut reasons = BTreeMap::new();
+        for skipped in &conversion.skipped {
+            *reasons.entry(skipped.reason).or_insert(0) += 1;
+        }
+        gh_annotate(
+            "warning",
+            &format!(
+                "{}: skipped {} {} lines from {} that cannot be converted ({})",
+                source.name,
+                conversion.skipped.len(),
+                format.as_str(),
+                input,
+                format_counts(&reasons)
+            ),
+        );
+    }
+    conversion.text
+}
+
+/// Fetch, convert and parse one input, applying the source's invalid-line
+/// policy. Returns the header-stripped text with invalid lines removed and
+/// its rules.
+fn load_input(
+    ctx: &Context,
+    source: &Source,
+    input: &Input,
+    rules_dir: &Path,
+) -> Result<(String, RuleSet)> {
+    let content = match input {
+        Input::Remote(url) => {
+            let content = ctx.download_text(source, url)?;
+            if let Some(sentinel) = &source.options.eof_sentinel {
+                check_sentinel(sentinel, &content)?;
+            }
+            content
+        }
+        Input::Local(path) => fs::read_to_string(rules_dir.join(path))?,
+    };
+    let content = convert_input(ctx, source, input, content);
+
+    // Strip original header and keep the rules that actually parse
+    let rule_content = strip_header(&content);
+    let rule_set = RuleSet::parse(&rule_content);
+    let rule_content = apply_invalid_policy(ctx, source, &rule_content, &rule_set)?;
+    Ok((rule_content, rule_set))
+}
+
+/// Download and process a single rule file
+/// Returns Ok(true) if the file was updated, Ok(false) if skipped (unchanged)
+fn sync_rule(
+    ctx: &Context,
+    source: &Source,
+    rules_dir: &Path,
+    unsupported: &mut UnsupportedCounts,
+) -> Result<bool> {
+    let category_dir = rules_dir.join(source.category.as_deref().unwrap_or_default());
+    ctx.ensure_dir(&category_dir)?;
+
+    // Always use .conf extension
+    let filename = format!("{}.conf", source.name);
+    let file_path = category_dir.join(&filename);
+
+    // Merge every input in order
+    let mut raw_count = 0;
+    let mut entries = Vec::new();
+    let mut texts = Vec::new();
+    for input in &source.inputs {
+        let (text, rule_set) = if source.is_composite() {
+            load_input(ctx, source, input, rules_dir).with_context(|| input.to_string())?
+        } else {
+            load_input(ctx, source, input, rules_dir)?
+        };
+        raw_count += rule_set.entries.len();
+        entries.extend(rule_set.entries);
+        texts.push(text);
+    }
+
+    if !source.exclude.is_empty() {
+        entries = exclude(entries, &source.exclude);
+    }
+
+    // Optimized and composite sets are written in canonical form, one rule
+    // per line; a plain source without optimization keeps the upstream text
+    let (rule_content, entries) = if source.options.optimize {
+        let entries = optimize(entries);
+        (format_rules(&entries), entries)
+    } else if source.is_composite() {
+        let entries = dedupe(entries);
+        (format_rules(&entries), entries)
+    } else {
+        (texts.concat(), entries)
+    };
+
+    let old_entries = existing_entries(&file_path);
+    {
+        let mut stats = ctx.stats.borrow_mut();
+        stats.old_entries = old_entries.as_ref().map(Vec::len);
+        stats.new_entries = Some(entries.len());
+    }
+    check_counts(
+        &source.options,
+        old_entries.as_ref().map(Vec::len),
+        entries.len(),
+    )?;
+
+    // Generate new header
+    let header = generate_header(ctx, &source.name, &source.inputs, raw_count, entries.len());
+
+    // Write file with new header + rules
+    let final_content = format!("{}\n{}", header, rule_content);
+
+    // Only write if content has actually changed (ignoring timestamp)
+    let mut changed = ctx.write_text(&file_path, &final_content)?;
+    if changed {
+        let old_entries = old_entries.unwrap_or_default();
+        ctx.changelog.borrow_mut().push(
+            format!(
+                "{}/{}",
+                source.category.as_deref().unwrap_or_default(),
+                source.name
+            ),
+            old_entries.len(),
+            RuleDiff::new(&old_entries, &entries),
+        );
+    }
+
+    for target in ExportTarget::ALL {
+        changed |= if source.options.exports.contains(&target) {
+            write_export(ctx, source, target, &entries, raw_count, unsupported)?
+        } else {
+            remove_exports(ctx, source, target, export::suffixes(target))?
+        };
+    }
+
+    Ok(changed)
+}
+
+/// Write the rule set in another client's format. Clash files sit next to
+/// the `.conf` file; other targets go to a parallel `rules-<target>/` tree.
+/// The header counts the rules actually exported; sing-box files carry no
+/// header since JSON has no comments.
+/// Returns true if any exported file changed.
+fn write_export(
+    ctx: &Context,
+    source: &Source,
+    target: ExportTarget,
+    entries: &[RuleEntry],
+    raw_count: usize,
+    unsupported: &mut UnsupportedCounts,
+) -> Result<bool> {
+    let (export, has_header) = match target {
+        ExportTarget::Clash => (clash::export(entries), true),
+        ExportTarget::Singbox => (
+            singbox::export(entries, source.options.singbox_version.0),
+            false,
+        ),
+        ExportTarget::Quantumultx => (quantumultx::export(entries), true),
+        ExportTarget::Loon => (loon::export(entries), true),
+    };
+    let dir = export_dir(ctx, source, target);
+
+    if !export.unsupported.is_empty() {
+        for entry in &export.unsupported {
+            ctx.detail(&format!("not exported to {}: {}", target.as_str(), entry));
+        }
+        let counts = export.unsupported_counts();
+        gh_annotate(
+            "warning",
+            &format!(
+                "{}: {} rules cannot be exported to {} ({})",
+                source.name,
+                export.unsupported.len(),
+                target.as_str(),
+                format_counts(&counts)
+            ),
+        );
+        let totals = unsupported.entry(target).or_default();
+        for (rule_type, count) in counts {
+            *totals.entry(rule_type).or_insert(0) += count;
+        }
+    }
+
+    if !export.files.is_empty() {
+        ctx.ensure_dir(&dir)?;
+    }
+
+    let header = has_header.then(|| {
+        let exported = entries.len() - export.unsupported.len();
+        generate_header(ctx, &source.name, &source.inputs, raw_count, exported)
+    });
+    let mut changed = false;
+    for file in &export.files {
+        let path = dir.join(format!("{}{}", source.name, file.suffix));
+        let content = match &header {
+            Some(header) => format!("{}\n{}", header, file.content),
+            None => file.content.clone(),
+        };
+        changed |= ctx.write_text(&path, &content)?;
+    }
+    Ok(remove_exports(ctx, source, target, &export.stale_suffixes(target))? || changed)
+}
+
+/// Directory `target` exports of `source` are written to
+fn export_dir(ctx: &Context, source: &Source, target: ExportTarget) -> PathBuf {
+    let category = source.category.as_deref().unwrap_or_default();
+    let tree = match target {
+        ExportTarget::Clash => "rules",
+        ExportTarget::Singbox => "rules-singbox",
+        ExportTarget::Quantumultx => "rules-quantumultx",
+        ExportTarget::Loon => "rules-loon",
+    };
+    ctx.root.join(tree).join(category)
+}
+
+/// Delete the `target` export files of `source` with the given suffixes,
+/// left over from an earlier run. Returns true if anything was deleted.
+fn remove_exports(
+    ctx: &Context,
+    source: &Source,
+    target: ExportTarget,
+    suffixes: &[&str],
+) -> Result<bool> {
+    let dir = export_dir(ctx, source, target);
+    let mut removed = false;
+    for suffix in suffixes {
+        let path = dir.join(format!("{}{}", source.name, suffix));
+        if path.is_file() {
+            ctx.detail(&format!("removed stale export {}", path.display()));
+            ctx.remove_file(&path)?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// `KEY: n, ...` for a per-type or per-reason count map
+fn format_counts(counts: &BTreeMap<&'static str, usize>) -> String {
+    counts
+        .iter()
+        .map(|(rule_type, count)| format!("{}: {}", rule_type, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Sync every selected rule source
+pub fn run(ctx: &Context) -> Result<()> {
+    log_status("Syncing", "rules from upstream...", LogLevel::Info);
+    let timer = Timer::start("syncing");
+
+    let rules_dir = ctx.root.join("rules");
+    ctx.ensure_dir(&rules_dir)?;
+
+    let sources = ctx.sources(SourceKind::Rule);
+    let mut unsupported = UnsupportedCounts::new();
+    let summary = Summary::run(ctx, &sources, |source| {
+        sync_rule(ctx, source, &rules_dir, &mut unsupported)
+    });
+    summary.report("rules", timer);
+
+    for (target, counts) in &unsupported {
+        let total: usize = counts.values().sum();
+        log_status(
+            "Unsupported",
+            &format!(
+                "{} rules not exported to {} ({})",
+                total,
+                target.as_str(),
+                format_counts(counts)
+            ),
+            LogLevel::Warning,
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Persistent HTTP metadata cache
+//!
+//! `.sync-cache.json` remembers, per URL, the validators the server sent
+//! with the last download that synced successfully, so the next run can make
+//! a conditional request. Per source it records what that sync was based on
+//! and which files it produced, so a `304 Not Modified` for every input can
+//! be trusted without reading the outputs.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Cache file name, relative to the project root
+pub const CACHE_FILE: &str = ".sync-cache.json";
+
+/// Lowercase hex SHA-256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Validators and content hash of the last successful download of a URL
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UrlEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub sha256: String,
+    /// Mirror that served the content, when it was not the URL itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<String>,
+}
+
+impl UrlEntry {
+    /// True if a conditional request can be made with this entry
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
+/// What the last successful sync of a source was based on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceEntry {
+    /// Hash of the source's definition and the tool version
+    pub fingerprint: String,
+    /// Files written or confirmed up to date, relative to the project root
+    pub outputs: Vec<PathBuf>,
+}
+
+/// Contents of `.sync-cache.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncCache {
+    #[serde(default)]
+    pub urls: BTreeMap<String, UrlEntry>,
+    /// Keyed by `kind/name`
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceEntry>,
+}
+
+/// Error reading or writing the cache file
+#[derive(Debug)]
+pub struct CacheError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl SyncCache {
+    /// Read the cache, treating a missing file as empty
+    pub fn load(path: &Path) -> Result<Self, CacheError> {
+        let error = |message: String| CacheError {
+            path: path.to_path_buf(),
+            message,
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(error(e.to_string())),
+        };
+        serde_json::from_str(&text).map_err(|e| error(e.to_string()))
+    }
+
+    /// Write the cache as pretty-printed JSON
+    pub fn save(&self, path: &Path) -> Result<(), CacheError> {
+        let error = |message: String| CacheError {
+            path: path.to_path_buf(),
+            message,
+        };
+        let mut json = serde_json::to_string_pretty(self).map_err(|e| error(e.to_string()))?;
+        json.push('\n');
+        fs::write(path, json).map_err(|e| error(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_and_missing_file() {
+        let dir = std::env::temp_dir().join(format!("surge-sync-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CACHE_FILE);
+
+        assert_eq!(SyncCache::load(&path).unwrap(), SyncCache::default());
+
+        let mut cache = SyncCache::default();
+        cache.urls.insert(
+            "https://e.com/a.conf".to_string(),
+            UrlEntry {
+                etag: Some("\"abc\"".to_string()),
+                last_modified: None,
+                sha256: sha256_hex(b"a"),
+                served_by: None,
+            },
+        );
+        cache.sources.insert(
+            "rule/a".to_string(),
+            SourceEntry {
+                fingerprint: "f".to_string(),
+                outputs: vec![PathBuf::from("rules/ai/a.conf")],
+            },
+        );
+        cache.save(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("last_modified"));
+        assert_eq!(SyncCache::load(&path).unwrap(), cache);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(SyncCache::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
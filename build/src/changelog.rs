@@ -0,0 +1,209 @@
+//! Semantic changelog of rule-set updates
+//!
+//! Compares the parsed rules of a set before and after a sync and renders
+//! the rules added and removed, grouped by rule type, as Markdown for
+//! `CHANGELOG-rules.md` or a GitHub Actions step summary. Removals large
+//! enough to suggest a broken upstream are called out.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::rule::RuleEntry;
+
+/// Changelog file name, relative to the project root
+pub const CHANGELOG_FILE: &str = "CHANGELOG-rules.md";
+
+/// Rules listed per set before the rest are summarized as a count
+pub const MAX_LISTED_RULES: usize = 200;
+
+/// Removals of at least this share of a set, in percent, are highlighted
+pub const LARGE_REMOVAL_PERCENT: usize = 10;
+
+/// Removals smaller than this are never highlighted
+pub const LARGE_REMOVAL_MIN: usize = 10;
+
+/// Rules added to and removed from one set, in rule-set order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleDiff {
+    pub added: Vec<RuleEntry>,
+    pub removed: Vec<RuleEntry>,
+}
+
+impl RuleDiff {
+    /// Compare the rules of a set before and after an update
+    pub fn new(old: &[RuleEntry], new: &[RuleEntry]) -> Self {
+        let old_set: HashSet<&RuleEntry> = old.iter().collect();
+        let new_set: HashSet<&RuleEntry> = new.iter().collect();
+        let mut seen = HashSet::new();
+        Self {
+            added: new
+                .iter()
+                .filter(|entry| !old_set.contains(entry) && seen.insert(*entry))
+                .cloned()
+                .collect(),
+            removed: old
+                .iter()
+                .filter(|entry| !new_set.contains(entry) && seen.insert(*entry))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// (added, removed) counts per rule type
+    pub fn counts_by_type(&self) -> BTreeMap<&'static str, (usize, usize)> {
+        let mut counts: BTreeMap<&'static str, (usize, usize)> = BTreeMap::new();
+        for entry in &self.added {
+            counts.entry(entry.rule.type_name()).or_default().0 += 1;
+        }
+        for entry in &self.removed {
+            counts.entry(entry.rule.type_name()).or_default().1 += 1;
+        }
+        counts
+    }
+}
+
+/// The change to one rule set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetChange {
+    /// `category/name` of the set
+    pub name: String,
+    /// Rules before the update; 0 for a new set
+    pub old_count: usize,
+    pub diff: RuleDiff,
+}
+
+impl SetChange {
+    /// True if the update removed a suspiciously large part of the set
+    pub fn is_large_removal(&self) -> bool {
+        let removed = self.diff.removed.len();
+        removed >= LARGE_REMOVAL_MIN && removed * 100 >= self.old_count * LARGE_REMOVAL_PERCENT
+    }
+}
+
+/// Changes to every rule set updated in a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changelog {
+    pub sets: Vec<SetChange>,
+}
+
+impl Changelog {
+    /// Record the change to a set; empty diffs are ignored
+    pub fn push(&mut self, name: String, old_count: usize, diff: RuleDiff) {
+        if !diff.is_empty() {
+            self.sets.push(SetChange {
+                name,
+                old_count,
+                diff,
+            });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Render one changelog entry titled with `timestamp`
+    pub fn to_markdown(&self, timestamp: &str) -> String {
+        let mut out = format!("## Rule changes {}\n\n", timestamp);
+        for set in &self.sets {
+            let diff = &set.diff;
+            out.push_str(&format!(
+                "### {}: +{} -{}\n\n",
+                set.name,
+                diff.added.len(),
+                diff.removed.len()
+            ));
+            if set.is_large_removal() {
+                out.push_str(&format!(
+                    "> **Large removal:** {} of {} rules removed\n\n",
+                    diff.removed.len(),
+                    set.old_count
+                ));
+            }
+
+            out.push_str("| Type | Added | Removed |\n| --- | ---: | ---: |\n");
+            for (rule_type, (added, removed)) in diff.counts_by_type() {
+                out.push_str(&format!("| {} | {} | {} |\n", rule_type, added, removed));
+            }
+
+            out.push_str("\n<details><summary>Rules</summary>\n\n```diff\n");
+            let lines = diff
+                .added
+                .iter()
+                .map(|entry| format!("+{}", entry))
+                .chain(diff.removed.iter().map(|entry| format!("-{}", entry)));
+            for line in lines.clone().take(MAX_LISTED_RULES) {
+                out.push_str(&line);
+                out.push('\n');
+            }
+            out.push_str("```\n");
+            let hidden = lines.count().saturating_sub(MAX_LISTED_RULES);
+            if hidden > 0 {
+                out.push_str(&format!("\n...and {} more\n", hidden));
+            }
+            out.push_str("\n</details>\n\n");
+        }
+        out
+    }
+}
+
+/// Insert `entry` at the top of an existing changelog, below its title
+pub fn prepend_entry(existing: &str, entry: &str) -> String {
+    const TITLE: &str = "# Rule changes\n";
+    let rest = existing.strip_prefix(TITLE).unwrap_or(existing);
+    format!("{}\n{}{}", TITLE, entry, rest.trim_start_matches('\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::RuleSet;
+
+    fn entries(text: &str) -> Vec<RuleEntry> {
+        RuleSet::parse(text).entries
+    }
+
+    #[test]
+    fn test_rule_diff_by_type() {
+        let old = entries("DOMAIN,a.com\nDOMAIN-SUFFIX,b.com\nIP-CIDR,1.1.1.0/24\n");
+        let new = entries("DOMAIN,a.com\nDOMAIN-SUFFIX,c.com\nDOMAIN-SUFFIX,c.com\n");
+        let diff = RuleDiff::new(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.removed.len(), 2);
+        let counts = diff.counts_by_type();
+        assert_eq!(counts["DOMAIN-SUFFIX"], (1, 1));
+        assert_eq!(counts["IP-CIDR"], (0, 1));
+        assert!(RuleDiff::new(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_markdown_highlights_large_removals() {
+        let old: Vec<RuleEntry> = (0..40)
+            .flat_map(|i| entries(&format!("DOMAIN,{}.com\n", i)))
+            .collect();
+        let mut changelog = Changelog::default();
+        changelog.push("ai/a".to_string(), 40, RuleDiff::new(&old, &old[..25]));
+        changelog.push("ai/b".to_string(), 40, RuleDiff::new(&old, &old[1..]));
+        changelog.push("ai/c".to_string(), 40, RuleDiff::new(&old, &old));
+        assert_eq!(changelog.sets.len(), 2);
+
+        let markdown = changelog.to_markdown("2026-01-01 00:00:00");
+        assert!(markdown.contains("### ai/a: +0 -15\n\n> **Large removal:** 15 of 40"));
+        assert!(markdown.contains("### ai/b: +0 -1\n\n| Type"));
+        assert!(markdown.contains("-DOMAIN,0.com\n"));
+    }
+
+    #[test]
+    fn test_prepend_entry() {
+        let first = prepend_entry("", "## Rule changes 1\n\n");
+        assert_eq!(first, "# Rule changes\n\n## Rule changes 1\n\n");
+        let second = prepend_entry(&first, "## Rule changes 2\n\n");
+        assert_eq!(
+            second,
+            "# Rule changes\n\n## Rule changes 2\n\n## Rule changes 1\n\n"
+        );
+    }
+}
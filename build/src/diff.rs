@@ -0,0 +1,174 @@
+//! Line diffs for dry runs
+//!
+//! Renders the change between the text on disk and the text a sync would
+//! write as unified diff hunks. Lines a caller marks as volatile, such as
+//! header timestamps, compare equal to each other so they never show up as
+//! changes on their own.
+
+/// Largest product of changed line counts diffed line by line; bigger
+/// changes are shown as one block replacement
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Edit script turning `old` into `new`
+fn edit_script(old: &[&str], new: &[&str], same: &dyn Fn(&str, &str) -> bool) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| same(a, b)).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let (n, m) = (old.len() - prefix - suffix, new.len() - prefix - suffix);
+
+    let mut ops: Vec<Op> = (0..prefix).map(|i| Op::Equal(i, i)).collect();
+    if n * m <= MAX_LCS_CELLS {
+        // lcs[i][j]: longest common subsequence of old[i..] and new[j..]
+        // within the changed middle
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if same(old[prefix + i], new[prefix + j]) {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && same(old[prefix + i], new[prefix + j]) {
+                ops.push(Op::Equal(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                ops.push(Op::Delete(prefix + i));
+                i += 1;
+            } else {
+                ops.push(Op::Insert(prefix + j));
+                j += 1;
+            }
+        }
+    } else {
+        ops.extend((prefix..prefix + n).map(Op::Delete));
+        ops.extend((prefix..prefix + m).map(Op::Insert));
+    }
+    ops.extend((0..suffix).map(|k| Op::Equal(prefix + n + k, prefix + m + k)));
+    ops
+}
+
+/// Unified diff hunks from `old` to `new` with `context` lines around each
+/// change, or an empty string if they only differ in volatile lines.
+/// Lines start with `' '`, `'-'` or `'+'`; hunks with an `@@` header.
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    is_volatile: impl Fn(&str) -> bool,
+    context: usize,
+) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let same = |a: &str, b: &str| a == b || (is_volatile(a) && is_volatile(b));
+    let ops = edit_script(&old, &new, &same);
+
+    let changed: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(k, _)| k)
+        .collect();
+
+    let mut out = String::new();
+    let mut k = 0;
+    while k < changed.len() {
+        // Grow the hunk while the next change is within two contexts
+        let start = changed[k].saturating_sub(context);
+        let mut end = changed[k];
+        while k + 1 < changed.len() && changed[k + 1] <= end + 2 * context + 1 {
+            k += 1;
+            end = changed[k];
+        }
+        let end = (end + context + 1).min(ops.len());
+        k += 1;
+
+        let hunk = &ops[start..end];
+        let old_start = hunk.iter().find_map(|op| match op {
+            Op::Equal(i, _) | Op::Delete(i) => Some(*i),
+            Op::Insert(_) => None,
+        });
+        let new_start = hunk.iter().find_map(|op| match op {
+            Op::Equal(_, j) | Op::Insert(j) => Some(*j),
+            Op::Delete(_) => None,
+        });
+        let old_count = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+        // Unified diffs number an empty side after the line it follows
+        let position = |start: Option<usize>, count: usize| match start {
+            Some(line) if count > 0 => line + 1,
+            _ => 0,
+        };
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            position(old_start, old_count),
+            old_count,
+            position(new_start, new_count),
+            new_count
+        ));
+        for op in hunk {
+            let (sign, line) = match *op {
+                Op::Equal(_, j) => (' ', new[j]),
+                Op::Delete(i) => ('-', old[i]),
+                Op::Insert(j) => ('+', new[j]),
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> String {
+        unified_diff(old, new, |line| line.starts_with("# Last Updated:"), 1)
+    }
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\n";
+        assert_eq!(
+            diff(old, new),
+            "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -8,1 +8,2 @@\n h\n+i\n"
+        );
+        assert_eq!(diff("", "x\n"), "@@ -0,0 +1,1 @@\n+x\n");
+    }
+
+    #[test]
+    fn test_volatile_lines_are_not_changes() {
+        let old = "# Last Updated: 1\nDOMAIN,a.com\n";
+        let new = "# Last Updated: 2\nDOMAIN,a.com\n";
+        assert_eq!(diff(old, new), "");
+        let new = "# Last Updated: 2\nDOMAIN,b.com\n";
+        assert_eq!(
+            diff(old, new),
+            "@@ -1,2 +1,2 @@\n # Last Updated: 2\n-DOMAIN,a.com\n+DOMAIN,b.com\n"
+        );
+    }
+}
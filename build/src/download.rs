@@ -0,0 +1,983 @@
+//! Shared download engine
+//!
+//! A [`Downloader`] reuses one HTTP client for every request and fetches
+//! batches of URLs on a bounded worker pool, with a separate cap on requests
+//! to the same host. Batch results are returned in request order, so callers
+//! log and summarize deterministically however the downloads complete.
+//! Transient failures are retried per [`RetryPolicy`], and failures are
+//! reported as a typed [`DownloadError`]. Batches are conditional requests
+//! when validators from the [`crate::cache`] are known for a URL. A request
+//! that fails falls through its mirrors in order, and a request pinned to a
+//! SHA-256 rejects any other content. The client's proxy, extra root
+//! certificates, User-Agent and timeouts come from a [`ClientConfig`].
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{
+    HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER,
+};
+use reqwest::{Certificate, NoProxy, Proxy, StatusCode, Url};
+
+use crate::cache::{sha256_hex, UrlEntry};
+
+/// Default number of downloads running at once
+pub const DEFAULT_JOBS: usize = 8;
+
+/// Most downloads running at once against a single host
+pub const PER_HOST_LIMIT: usize = 4;
+
+/// Default number of retries after a transient failure
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Largest response body accepted
+pub const MAX_BODY_BYTES: u64 = 128 * 1024 * 1024;
+
+/// Default timeout for a whole request, in seconds
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Default timeout for establishing a connection, in seconds
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// User-Agent sent when none is configured
+pub const DEFAULT_USER_AGENT: &str = concat!("surge-sync/", env!("CARGO_PKG_VERSION"));
+
+/// Environment variables overriding [`ClientConfig`] settings
+pub const ENV_PROXY: &str = "SURGE_SYNC_PROXY";
+pub const ENV_CA_CERTS: &str = "SURGE_SYNC_CA_CERTS";
+pub const ENV_USER_AGENT: &str = "SURGE_SYNC_USER_AGENT";
+pub const ENV_TIMEOUT: &str = "SURGE_SYNC_TIMEOUT";
+pub const ENV_CONNECT_TIMEOUT: &str = "SURGE_SYNC_CONNECT_TIMEOUT";
+
+/// Check that `url` is a proxy URL the client can use
+pub fn check_proxy(url: &str) -> Result<(), String> {
+    match Url::parse(url) {
+        Ok(parsed)
+            if matches!(
+                parsed.scheme(),
+                "http" | "https" | "socks4" | "socks4a" | "socks5" | "socks5h"
+            ) =>
+        {
+            Ok(())
+        }
+        Ok(parsed) => Err(format!(
+            "unsupported proxy scheme `{}` in `{}`",
+            parsed.scheme(),
+            url
+        )),
+        Err(e) => Err(format!("malformed proxy URL `{}`: {}", url, e)),
+    }
+}
+
+/// Check that `value` can be sent as a User-Agent header
+pub fn check_user_agent(value: &str) -> Result<(), String> {
+    if value.trim().is_empty() || HeaderValue::from_str(value).is_err() {
+        return Err(format!("invalid User-Agent `{}`", value));
+    }
+    Ok(())
+}
+
+/// Invalid HTTP client setting
+#[derive(Debug)]
+pub struct ClientConfigError {
+    /// Config key or environment variable the value came from
+    pub setting: String,
+    pub message: String,
+}
+
+impl fmt::Display for ClientConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.setting, self.message)
+    }
+}
+
+impl std::error::Error for ClientConfigError {}
+
+/// Settings of the shared HTTP client. Unset values fall back to the
+/// defaults; without an explicit proxy the standard `HTTPS_PROXY`,
+/// `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` variables apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// http(s) or socks proxy for every request
+    pub proxy: Option<String>,
+    /// PEM files with root certificates trusted besides the system ones
+    pub ca_certs: Vec<PathBuf>,
+    pub user_agent: Option<String>,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+}
+
+impl ClientConfig {
+    /// Override settings from the `SURGE_SYNC_*` environment variables
+    pub fn apply_env(&mut self) -> Result<(), ClientConfigError> {
+        self.apply_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Override settings from variables looked up with `var`; empty values
+    /// are ignored
+    fn apply_vars(
+        &mut self,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), ClientConfigError> {
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let error = |setting: &str, message: String| ClientConfigError {
+            setting: setting.to_string(),
+            message,
+        };
+        let seconds = |name: &str, value: String| match value.trim().parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+            _ => Err(error(
+                name,
+                format!("expected a positive number of seconds, got `{}`", value),
+            )),
+        };
+
+        if let Some(proxy) = var(ENV_PROXY) {
+            check_proxy(&proxy).map_err(|message| error(ENV_PROXY, message))?;
+            self.proxy = Some(proxy);
+        }
+        if let Some(paths) = var(ENV_CA_CERTS) {
+            self.ca_certs = std::env::split_paths(&paths).collect();
+        }
+        if let Some(user_agent) = var(ENV_USER_AGENT) {
+            check_user_agent(&user_agent).map_err(|message| error(ENV_USER_AGENT, message))?;
+            self.user_agent = Some(user_agent);
+        }
+        if let Some(value) = var(ENV_TIMEOUT) {
+            self.timeout = Some(seconds(ENV_TIMEOUT, value)?);
+        }
+        if let Some(value) = var(ENV_CONNECT_TIMEOUT) {
+            self.connect_timeout = Some(seconds(ENV_CONNECT_TIMEOUT, value)?);
+        }
+        Ok(())
+    }
+
+    /// Build the HTTP client these settings describe
+    fn build_client(&self) -> Result<Client, ClientConfigError> {
+        let error = |setting: &str, message: String| ClientConfigError {
+            setting: setting.to_string(),
+            message,
+        };
+
+        let mut builder = Client::builder()
+            .user_agent(self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            .timeout(
+                self.timeout
+                    .unwrap_or(Duration::from_secs(DEFAULT_TIMEOUT_SECS)),
+            )
+            .connect_timeout(
+                self.connect_timeout
+                    .unwrap_or(Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS)),
+            );
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy.as_str())
+                .map_err(|e| error("proxy", e.to_string()))?
+                .no_proxy(NoProxy::from_env());
+            builder = builder.proxy(proxy);
+        }
+        for path in &self.ca_certs {
+            let pem = std::fs::read(path)
+                .map_err(|e| error("ca_certs", format!("{}: {}", path.display(), e)))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| error("ca_certs", format!("{}: {}", path.display(), e)))?;
+            if certs.is_empty() {
+                return Err(error(
+                    "ca_certs",
+                    format!("{}: no PEM certificates found", path.display()),
+                ));
+            }
+            builder = builder.tls_certs_merge(certs);
+        }
+        builder
+            .build()
+            .map_err(|e| error("http client", e.to_string()))
+    }
+}
+
+/// Why a download failed
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The host name could not be resolved
+    Dns { url: String, message: String },
+    /// The connection could not be established
+    Connect { url: String, message: String },
+    /// The request or response body timed out
+    Timeout { url: String },
+    /// The server answered with a non-success status
+    Status {
+        url: String,
+        status: StatusCode,
+        /// Delay requested by the server through `Retry-After`
+        retry_after: Option<Duration>,
+    },
+    /// The response body exceeds [`MAX_BODY_BYTES`]
+    BodyTooLarge { url: String, limit: u64 },
+    /// Text was expected but the body is not UTF-8
+    NotUtf8 { url: String },
+    /// Any other request failure
+    Request { url: String, message: String },
+    /// The content does not match the source's `sha256` pin
+    Checksum {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// True if `cause`, the root of a connect failure, comes from the resolver.
+/// The standard library reports a failed `getaddrinfo` as an `io::Error`
+/// without an OS error code, while a failed socket connect always has one.
+fn is_resolver_error(cause: &(dyn std::error::Error + 'static)) -> bool {
+    cause
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.raw_os_error().is_none())
+}
+
+impl DownloadError {
+    /// Classify a request failure
+    fn from_reqwest(url: &str, error: reqwest::Error) -> Self {
+        let url = url.to_string();
+        if error.is_timeout() {
+            return DownloadError::Timeout { url };
+        }
+
+        // The root cause carries the detail reqwest's own message leaves out
+        let mut cause: &(dyn std::error::Error + 'static) = &error;
+        while let Some(source) = cause.source() {
+            cause = source;
+        }
+        let message = cause.to_string();
+
+        if !error.is_connect() {
+            DownloadError::Request { url, message }
+        } else if is_resolver_error(cause) {
+            DownloadError::Dns { url, message }
+        } else {
+            DownloadError::Connect { url, message }
+        }
+    }
+
+    /// True if the same request may succeed when tried again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::Dns { .. }
+            | DownloadError::Connect { .. }
+            | DownloadError::Timeout { .. } => true,
+            DownloadError::Status { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            DownloadError::BodyTooLarge { .. }
+            | DownloadError::NotUtf8 { .. }
+            | DownloadError::Request { .. }
+            | DownloadError::Checksum { .. } => false,
+        }
+    }
+
+    /// Short label for summaries, e.g. `timeout` or `HTTP 503`
+    pub fn reason(&self) -> String {
+        match self {
+            DownloadError::Dns { .. } => "DNS".to_string(),
+            DownloadError::Connect { .. } => "connect".to_string(),
+            DownloadError::Timeout { .. } => "timeout".to_string(),
+            DownloadError::Status { status, .. } => format!("HTTP {}", status.as_u16()),
+            DownloadError::BodyTooLarge { .. } => "body too large".to_string(),
+            DownloadError::NotUtf8 { .. } => "not UTF-8".to_string(),
+            DownloadError::Request { .. } => "request".to_string(),
+            DownloadError::Checksum { .. } => "sha256 mismatch".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Dns { url, message } => {
+                write!(f, "DNS lookup failed for {}: {}", url, message)
+            }
+            DownloadError::Connect { url, message } => {
+                write!(f, "could not connect to {}: {}", url, message)
+            }
+            DownloadError::Timeout { url } => write!(f, "timed out fetching {}", url),
+            DownloadError::Status { url, status, .. } => write!(f, "HTTP {} for {}", status, url),
+            DownloadError::BodyTooLarge { url, limit } => {
+                write!(f, "response from {} exceeds {} bytes", url, limit)
+            }
+            DownloadError::NotUtf8 { url } => write!(f, "response from {} is not UTF-8", url),
+            DownloadError::Request { url, message } => {
+                write!(f, "request to {} failed: {}", url, message)
+            }
+            DownloadError::Checksum {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "content of {} does not match its pin: expected sha256 {}, got {}",
+                url, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+/// Body of a download or the reason it failed
+pub type DownloadResult = Result<Vec<u8>, DownloadError>;
+
+/// Successful outcome of a possibly conditional request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    Body(Vec<u8>),
+    /// The server answered `304 Not Modified`
+    NotModified,
+}
+
+/// Outcome of a possibly conditional request
+pub type FetchResult = Result<Fetched, DownloadError>;
+
+/// Public CDNs serving GitHub repository files, as `{host}/gh/` prefixes
+const GITHUB_CDNS: &[&str] = &["https://cdn.jsdelivr.net", "https://fastly.jsdelivr.net"];
+
+/// jsDelivr-style CDN copies of a GitHub-hosted file URL: raw
+/// `raw.githubusercontent.com` links and `github.com/.../raw|blob/...` pages.
+/// Other URLs have none.
+pub fn cdn_mirrors(url: &str) -> Vec<String> {
+    let Ok(parsed) = Url::parse(url) else {
+        return Vec::new();
+    };
+    let segments: Vec<&str> = parsed.path().trim_start_matches('/').split('/').collect();
+    let (user, repo, rest) = match (parsed.host_str(), segments.as_slice()) {
+        (Some("raw.githubusercontent.com"), [user, repo, rest @ ..]) => (*user, *repo, rest),
+        (Some("github.com"), [user, repo, "raw" | "blob", rest @ ..]) => (*user, *repo, rest),
+        _ => return Vec::new(),
+    };
+    let (git_ref, path) = match rest {
+        ["refs", "heads" | "tags", git_ref, path @ ..] => (*git_ref, path),
+        [git_ref, path @ ..] => (*git_ref, path),
+        [] => return Vec::new(),
+    };
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    GITHUB_CDNS
+        .iter()
+        .map(|cdn| {
+            format!(
+                "{}/gh/{}/{}@{}/{}",
+                cdn,
+                user,
+                repo,
+                git_ref,
+                path.join("/")
+            )
+        })
+        .collect()
+}
+
+/// A URL to download, the mirrors to fall back to and the retry policy
+/// applied to each of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub url: String,
+    pub mirrors: Vec<String>,
+    pub retry: RetryPolicy,
+    /// Lowercase hex SHA-256 the content must have, checked by
+    /// [`Downloader::get`]
+    pub sha256: Option<String>,
+}
+
+/// How often and how long to wait before retrying a transient failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each one after it
+    pub base_delay: Duration,
+    /// Upper bound for any single delay, including `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_RETRIES,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Default delays with `retries` retries
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based): the server's
+    /// `Retry-After` if given, otherwise exponential backoff with jitter
+    /// between half and all of the backoff
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(after) = retry_after {
+            return after.min(self.max_delay);
+        }
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay);
+        let half = backoff / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+/// Parse a `Retry-After` value: delay seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.signed_duration_since(chrono::Utc::now());
+    Some(delay.to_std().unwrap_or_default())
+}
+
+/// Counts in-flight requests per host and blocks callers over the limit
+struct HostSlots {
+    limit: usize,
+    active: Mutex<HashMap<String, usize>>,
+    freed: Condvar,
+}
+
+/// A claimed slot, released when dropped
+struct HostSlot<'a> {
+    slots: &'a HostSlots,
+    host: String,
+}
+
+impl HostSlots {
+    fn acquire(&self, host: &str) -> HostSlot<'_> {
+        let mut active = self.active.lock().unwrap();
+        while active.get(host).copied().unwrap_or(0) >= self.limit {
+            active = self.freed.wait(active).unwrap();
+        }
+        *active.entry(host.to_string()).or_insert(0) += 1;
+        HostSlot {
+            slots: self,
+            host: host.to_string(),
+        }
+    }
+}
+
+impl Drop for HostSlot<'_> {
+    fn drop(&mut self) {
+        let mut active = self.slots.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.host) {
+            *count -= 1;
+        }
+        self.slots.freed.notify_all();
+    }
+}
+
+/// HTTP downloader shared by every sync tool
+pub struct Downloader {
+    client: Client,
+    jobs: usize,
+    hosts: HostSlots,
+    /// Results of [`Downloader::prefetch`] not yet claimed by [`Downloader::get`]
+    prefetched: Mutex<HashMap<String, FetchResult>>,
+    /// Validators sent with conditional requests, keyed by URL
+    validators: HashMap<String, UrlEntry>,
+    /// Validators received with full responses during this run
+    received: Mutex<HashMap<String, UrlEntry>>,
+}
+
+impl Downloader {
+    /// Create a downloader running at most `jobs` downloads at once with a
+    /// client built from `config`
+    pub fn new(jobs: usize, config: &ClientConfig) -> Result<Self, ClientConfigError> {
+        let client = config.build_client()?;
+        Ok(Self {
+            client,
+            jobs: jobs.max(1),
+            hosts: HostSlots {
+                limit: PER_HOST_LIMIT,
+                active: Mutex::new(HashMap::new()),
+                freed: Condvar::new(),
+            },
+            prefetched: Mutex::new(HashMap::new()),
+            validators: HashMap::new(),
+            received: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Use cached validators for conditional requests
+    pub fn with_validators(mut self, validators: HashMap<String, UrlEntry>) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    /// Validators and content hash from this run's full download of `url`
+    pub fn received(&self, url: &str) -> Option<UrlEntry> {
+        self.received.lock().unwrap().get(url).cloned()
+    }
+
+    /// URL that served this run's full download of `url`: the URL itself
+    /// or one of its mirrors
+    pub fn served_by(&self, url: &str) -> Option<String> {
+        let entry = self.received(url)?;
+        Some(entry.served_by.unwrap_or_else(|| url.to_string()))
+    }
+
+    /// Number of worker threads used for batches
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Read the body, refusing anything over [`MAX_BODY_BYTES`]
+    fn read_body(url: &str, response: Response) -> DownloadResult {
+        let too_large = || DownloadError::BodyTooLarge {
+            url: url.to_string(),
+            limit: MAX_BODY_BYTES,
+        };
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_BODY_BYTES)
+        {
+            return Err(too_large());
+        }
+
+        let mut body = Vec::new();
+        response
+            .take(MAX_BODY_BYTES + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| match e.into_inner() {
+                Some(inner) => match inner.downcast::<reqwest::Error>() {
+                    Ok(error) => DownloadError::from_reqwest(url, *error),
+                    Err(inner) => DownloadError::Request {
+                        url: url.to_string(),
+                        message: inner.to_string(),
+                    },
+                },
+                None => DownloadError::Request {
+                    url: url.to_string(),
+                    message: "failed to read response body".to_string(),
+                },
+            })?;
+        if body.len() as u64 > MAX_BODY_BYTES {
+            return Err(too_large());
+        }
+        Ok(body)
+    }
+
+    /// Make a single attempt at `url`, a mirror of `primary` or `primary`
+    /// itself, waiting for a free slot on its host. A `conditional` request
+    /// sends the cached validators when `url` served the cached content.
+    fn fetch_once(&self, url: &str, primary: &str, conditional: bool) -> FetchResult {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let _slot = self.hosts.acquire(&host);
+
+        let mut request = self.client.get(url);
+        let cached = self
+            .validators
+            .get(primary)
+            .filter(|cached| conditional && cached.served_by.as_deref().unwrap_or(primary) == url);
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request
+            .send()
+            .map_err(|e| DownloadError::from_reqwest(url, e))?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED && conditional {
+            return Ok(Fetched::NotModified);
+        }
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            return Err(DownloadError::Status {
+                url: url.to_string(),
+                status,
+                retry_after,
+            });
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+
+        let body = Self::read_body(url, response)?;
+        self.received.lock().unwrap().insert(
+            primary.to_string(),
+            UrlEntry {
+                etag,
+                last_modified,
+                sha256: sha256_hex(&body),
+                served_by: (url != primary).then(|| url.to_string()),
+            },
+        );
+        Ok(Fetched::Body(body))
+    }
+
+    /// Download `url`, retrying transient failures per `retry`
+    fn fetch(
+        &self,
+        url: &str,
+        primary: &str,
+        retry: RetryPolicy,
+        conditional: bool,
+    ) -> FetchResult {
+        let mut attempt = 0;
+        loop {
+            match self.fetch_once(url, primary, conditional) {
+                Err(error) if error.is_transient() && attempt < retry.retries => {
+                    let retry_after = match &error {
+                        DownloadError::Status { retry_after, .. } => *retry_after,
+                        _ => None,
+                    };
+                    thread::sleep(retry.delay(attempt, retry_after));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Download a request's URL, falling through its mirrors in order.
+    /// If every URL fails, the error for the URL itself is returned.
+    fn fetch_request(&self, request: &Request, conditional: bool) -> FetchResult {
+        let mut first_error = None;
+        for url in std::iter::once(&request.url).chain(&request.mirrors) {
+            match self.fetch(url, &request.url, request.retry, conditional) {
+                Ok(fetched) => return Ok(fetched),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        Err(first_error.expect("a request has at least one URL"))
+    }
+
+    /// Download every URL on the worker pool with conditional requests,
+    /// returning results in request order
+    pub fn fetch_all(&self, requests: &[Request]) -> Vec<FetchResult> {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<FetchResult>>> =
+            Mutex::new(requests.iter().map(|_| None).collect());
+
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.min(requests.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(request) = requests.get(i) else {
+                        break;
+                    };
+                    let result = self.fetch_request(request, true);
+                    results.lock().unwrap()[i] = Some(result);
+                });
+            }
+        });
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|result| result.expect("every URL is fetched"))
+            .collect()
+    }
+
+    /// Download `requests` in parallel ahead of time; later
+    /// [`Downloader::get`] calls for them return the stored result
+    pub fn prefetch(&self, requests: &[Request]) {
+        let results = self.fetch_all(requests);
+        let mut prefetched = self.prefetched.lock().unwrap();
+        for (request, result) in requests.iter().zip(results) {
+            prefetched.insert(request.url.clone(), result);
+        }
+    }
+
+    /// True if a conditional request was answered `304 Not Modified`.
+    /// Makes the request now unless it was prefetched; its result is kept
+    /// for [`Downloader::get`].
+    pub fn not_modified(&self, request: &Request) -> bool {
+        let url = &request.url;
+        if !self.prefetched.lock().unwrap().contains_key(url) {
+            let result = self.fetch_request(request, true);
+            self.prefetched.lock().unwrap().insert(url.clone(), result);
+        }
+        matches!(
+            self.prefetched.lock().unwrap().get(url),
+            Some(Ok(Fetched::NotModified))
+        )
+    }
+
+    /// Download binary content, using a prefetched body when there is one.
+    /// Content that was only confirmed unchanged is downloaded in full.
+    pub fn get(&self, request: &Request) -> DownloadResult {
+        let prefetched = self.prefetched.lock().unwrap().remove(&request.url);
+        let result = match prefetched {
+            Some(Ok(Fetched::NotModified)) | None => self.fetch_request(request, false),
+            Some(result) => result,
+        };
+        let body = match result? {
+            Fetched::Body(body) => body,
+            Fetched::NotModified => unreachable!("unconditional requests always return a body"),
+        };
+        if let Some(expected) = &request.sha256 {
+            let actual = sha256_hex(&body);
+            if &actual != expected {
+                return Err(DownloadError::Checksum {
+                    url: request.url.clone(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok(body)
+    }
+
+    /// Download UTF-8 text content
+    pub fn get_text(&self, request: &Request) -> Result<String, DownloadError> {
+        let bytes = self.get(request)?;
+        String::from_utf8(bytes).map_err(|_| DownloadError::NotUtf8 {
+            url: request.url.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_slots_cap_concurrency() {
+        let slots = HostSlots {
+            limit: 2,
+            active: Mutex::new(HashMap::new()),
+            freed: Condvar::new(),
+        };
+        let peak = AtomicUsize::new(0);
+        let current = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _slot = slots.acquire("example.com");
+                    let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    current.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            // Another host is never blocked by the busy one
+            let _other = slots.acquire("other.com");
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(slots.active.lock().unwrap()["example.com"], 0);
+    }
+
+    #[test]
+    fn test_fetch_all_keeps_request_order() {
+        let downloader = Downloader::new(4, &ClientConfig::default()).unwrap();
+        // Nothing listens on port 1, so every request fails fast
+        let requests: Vec<Request> = (0..6)
+            .map(|i| Request {
+                url: format!("http://127.0.0.1:1/{}", i),
+                mirrors: vec![format!("http://127.0.0.1:1/mirror/{}", i)],
+                retry: RetryPolicy::new(0),
+                sha256: None,
+            })
+            .collect();
+        let results = downloader.fetch_all(&requests);
+        assert_eq!(results.len(), 6);
+        for (request, result) in requests.iter().zip(&results) {
+            // Every mirror was tried, but the error names the URL itself
+            match result {
+                Err(DownloadError::Connect { url, .. }) => assert_eq!(url, &request.url),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unresolvable_host_is_dns_error() {
+        let downloader = Downloader::new(1, &ClientConfig::default()).unwrap();
+        // The .invalid TLD never resolves
+        let request = Request {
+            url: "http://surge-sync.invalid/a.conf".to_string(),
+            mirrors: Vec::new(),
+            retry: RetryPolicy::new(0),
+            sha256: None,
+        };
+        match &downloader.fetch_all(std::slice::from_ref(&request))[0] {
+            Err(error @ DownloadError::Dns { url, .. }) => {
+                assert_eq!(url, &request.url);
+                assert_eq!(error.reason(), "DNS");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_get_checks_sha256_pin() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/a.conf", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc",
+                    )
+                    .unwrap();
+            }
+        });
+
+        let downloader = Downloader::new(1, &ClientConfig::default()).unwrap();
+        let mut request = Request {
+            url: url.clone(),
+            mirrors: Vec::new(),
+            retry: RetryPolicy::new(0),
+            sha256: Some(sha256_hex(b"abc")),
+        };
+        assert_eq!(downloader.get(&request).unwrap(), b"abc");
+
+        request.sha256 = Some(sha256_hex(b"old"));
+        match downloader.get(&request) {
+            Err(DownloadError::Checksum { actual, .. }) => assert_eq!(actual, sha256_hex(b"abc")),
+            other => panic!("unexpected result {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_cdn_mirrors() {
+        assert_eq!(
+            cdn_mirrors("https://raw.githubusercontent.com/u/r/refs/heads/main/Surge/a%20b.conf"),
+            vec![
+                "https://cdn.jsdelivr.net/gh/u/r@main/Surge/a%20b.conf",
+                "https://fastly.jsdelivr.net/gh/u/r@main/Surge/a%20b.conf"
+            ]
+        );
+        assert_eq!(
+            cdn_mirrors("https://github.com/u/r/raw/v1.0/x.sgmodule")[0],
+            "https://cdn.jsdelivr.net/gh/u/r@v1.0/x.sgmodule"
+        );
+        assert!(cdn_mirrors("https://github.com/u/r/releases/latest/download/x").is_empty());
+        assert!(cdn_mirrors("https://ruleset.skk.moe/List/non_ip/ai.conf").is_empty());
+    }
+
+    #[test]
+    fn test_backoff_and_retry_after() {
+        let retry = RetryPolicy::default();
+        for attempt in 0..4 {
+            let backoff = Duration::from_secs(1 << attempt);
+            let delay = retry.delay(attempt, None);
+            assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+        }
+        assert!(retry.delay(10, None) <= retry.max_delay);
+        assert_eq!(
+            retry.delay(0, Some(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            retry.delay(0, Some(Duration::from_secs(600))),
+            retry.max_delay
+        );
+
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        let status = |code: u16| DownloadError::Status {
+            url: "https://e.com".to_string(),
+            status: StatusCode::from_u16(code).unwrap(),
+            retry_after: None,
+        };
+        assert!(status(429).is_transient());
+        assert!(status(503).is_transient());
+        assert!(!status(404).is_transient());
+        assert_eq!(status(503).reason(), "HTTP 503");
+        assert_eq!(
+            status(404).to_string(),
+            "HTTP 404 Not Found for https://e.com"
+        );
+        let not_utf8 = DownloadError::NotUtf8 { url: String::new() };
+        assert!(!not_utf8.is_transient());
+    }
+
+    #[test]
+    fn test_client_config_env_overrides() {
+        let vars: HashMap<&str, &str> = [
+            (ENV_PROXY, "http://proxy.corp:3128"),
+            (ENV_USER_AGENT, ""),
+            (ENV_TIMEOUT, "90"),
+        ]
+        .into_iter()
+        .collect();
+        let mut config = ClientConfig {
+            user_agent: Some("custom".to_string()),
+            ..ClientConfig::default()
+        };
+        config
+            .apply_vars(|name| vars.get(name).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy.corp:3128"));
+        assert_eq!(config.user_agent.as_deref(), Some("custom"));
+        assert_eq!(config.timeout, Some(Duration::from_secs(90)));
+        assert!(config.build_client().is_ok());
+
+        let err = ClientConfig::default()
+            .apply_vars(|name| (name == ENV_CONNECT_TIMEOUT).then(|| "soon".to_string()))
+            .unwrap_err();
+        assert_eq!(err.setting, ENV_CONNECT_TIMEOUT);
+
+        let config = ClientConfig {
+            ca_certs: vec![PathBuf::from("/nonexistent/ca.pem")],
+            ..ClientConfig::default()
+        };
+        assert_eq!(config.build_client().unwrap_err().setting, "ca_certs");
+    }
+}
@@ -0,0 +1,211 @@
+//! Clash / mihomo rule-provider export
+//!
+//! Produces `payload:` YAML files: always the `classical` behavior, plus the
+//! `domain` behavior for purely domain-based sets and the `ipcidr` behavior
+//! for IP-only sets.
+
+use crate::rule::{Rule, RuleEntry};
+
+use super::{Export, ExportFile};
+
+/// Quote a YAML scalar with single quotes
+fn yaml_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Render a `payload:` document from already formatted items
+fn payload(items: &[String]) -> String {
+    if items.is_empty() {
+        return "payload: []\n".to_string();
+    }
+    let mut out = String::from("payload:\n");
+    for item in items {
+        out.push_str("  - ");
+        out.push_str(&yaml_quote(item));
+        out.push('\n');
+    }
+    out
+}
+
+/// Translate a Surge `DOMAIN-WILDCARD` pattern into an anchored regex
+fn wildcard_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+fn group(entries: &[RuleEntry]) -> Option<String> {
+    let parts: Option<Vec<String>> = entries
+        .iter()
+        .map(|entry| classical(entry).map(|line| format!("({})", line)))
+        .collect();
+    Some(format!("({})", parts?.join(",")))
+}
+
+/// Convert one rule to a `classical` payload line, or `None` if Clash has
+/// no equivalent
+fn classical(entry: &RuleEntry) -> Option<String> {
+    let (rule_type, value) = match &entry.rule {
+        Rule::Domain(v) => ("DOMAIN", v.clone()),
+        Rule::DomainSuffix(v) => ("DOMAIN-SUFFIX", v.clone()),
+        Rule::DomainKeyword(v) => ("DOMAIN-KEYWORD", v.clone()),
+        Rule::DomainWildcard(v) => ("DOMAIN-REGEX", wildcard_to_regex(v)),
+        Rule::IpCidr(net) => ("IP-CIDR", net.to_string()),
+        Rule::IpCidr6(net) => ("IP-CIDR6", net.to_string()),
+        Rule::IpAsn(asn) => ("IP-ASN", asn.to_string()),
+        Rule::Geoip(v) => ("GEOIP", v.clone()),
+        Rule::ProcessName(v) => ("PROCESS-NAME", v.clone()),
+        Rule::DestPort(v) => ("DST-PORT", v.clone()),
+        Rule::And(entries) => ("AND", group(entries)?),
+        Rule::Or(entries) => ("OR", group(entries)?),
+        Rule::Not(entry) => ("NOT", group(std::slice::from_ref(entry.as_ref()))?),
+        Rule::Other {
+            rule_type: rule_type @ ("SRC-PORT" | "IN-PORT"),
+            value,
+        } => (*rule_type, value.clone()),
+        Rule::UserAgent(_) | Rule::UrlRegex(_) | Rule::Other { .. } => return None,
+    };
+
+    let resolves = matches!(
+        entry.rule,
+        Rule::IpCidr(_) | Rule::IpCidr6(_) | Rule::IpAsn(_) | Rule::Geoip(_)
+    );
+    if resolves && entry.options.no_resolve {
+        Some(format!("{},{},no-resolve", rule_type, value))
+    } else {
+        Some(format!("{},{}", rule_type, value))
+    }
+}
+
+/// Export a rule set as Clash rule-provider payloads
+pub fn export(entries: &[RuleEntry]) -> Export {
+    let mut result = Export::default();
+    let mut lines = Vec::with_capacity(entries.len());
+    let mut exported = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        match classical(entry) {
+            Some(line) => {
+                lines.push(line);
+                exported.push(entry);
+            }
+            None => result.unsupported.push(entry.clone()),
+        }
+    }
+
+    result.files.push(ExportFile {
+        suffix: ".yaml",
+        content: payload(&lines),
+    });
+
+    if exported.is_empty() {
+        return result;
+    }
+
+    let domains: Option<Vec<String>> = exported
+        .iter()
+        .map(|entry| match &entry.rule {
+            Rule::Domain(v) => Some(v.clone()),
+            Rule::DomainSuffix(v) => Some(format!("+.{}", v)),
+            _ => None,
+        })
+        .collect();
+    if let Some(domains) = domains {
+        result.files.push(ExportFile {
+            suffix: ".domain.yaml",
+            content: payload(&domains),
+        });
+    }
+
+    let cidrs: Option<Vec<String>> = exported
+        .iter()
+        .map(|entry| match &entry.rule {
+            Rule::IpCidr(net) => Some(net.to_string()),
+            Rule::IpCidr6(net) => Some(net.to_string()),
+            _ => None,
+        })
+        .collect();
+    if let Some(cidrs) = cidrs {
+        result.files.push(ExportFile {
+            suffix: ".ipcidr.yaml",
+            content: payload(&cidrs),
+        });
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ExportTarget;
+    use crate::rule::RuleSet;
+
+    fn export_text(text: &str) -> Export {
+        export(&RuleSet::parse(text).entries)
+    }
+
+    #[test]
+    fn test_classical_and_domain_behaviors() {
+        let result = export_text("DOMAIN,a.com\nDOMAIN-SUFFIX,b.com\n");
+        let suffixes: Vec<&str> = result.files.iter().map(|f| f.suffix).collect();
+        assert_eq!(suffixes, vec![".yaml", ".domain.yaml"]);
+        assert_eq!(
+            result.files[0].content,
+            "payload:\n  - 'DOMAIN,a.com'\n  - 'DOMAIN-SUFFIX,b.com'\n"
+        );
+        assert_eq!(
+            result.files[1].content,
+            "payload:\n  - 'a.com'\n  - '+.b.com'\n"
+        );
+    }
+
+    #[test]
+    fn test_stale_behaviors() {
+        let result = export_text("DOMAIN,a.com\n");
+        assert_eq!(result.stale_suffixes(ExportTarget::Clash), [".ipcidr.yaml"]);
+
+        // A domain-only set gaining an IP-CIDR loses its domain file
+        let result = export_text("DOMAIN,a.com\nIP-CIDR,10.0.0.0/8\n");
+        assert_eq!(
+            result.stale_suffixes(ExportTarget::Clash),
+            [".domain.yaml", ".ipcidr.yaml"]
+        );
+    }
+
+    #[test]
+    fn test_ipcidr_behavior_and_mapping() {
+        let result = export_text("IP-CIDR,1.1.1.0/24,no-resolve\nIP-CIDR6,2001:db8::/32\n");
+        assert_eq!(result.files[1].suffix, ".ipcidr.yaml");
+        assert_eq!(
+            result.files[0].content,
+            "payload:\n  - 'IP-CIDR,1.1.1.0/24,no-resolve'\n  - 'IP-CIDR6,2001:db8::/32'\n"
+        );
+
+        let result = export_text("DEST-PORT,443\nDOMAIN-WILDCARD,*.a?.com\n");
+        assert_eq!(
+            result.files[0].content,
+            "payload:\n  - 'DST-PORT,443'\n  - 'DOMAIN-REGEX,^.*\\.a.\\.com$'\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_rules_are_reported() {
+        let result = export_text(
+            "DOMAIN,a.com\nUSER-AGENT,*bili*\nURL-REGEX,^https://x\nAND,((DOMAIN,a.com),(USER-AGENT,x))\n",
+        );
+        assert_eq!(result.unsupported.len(), 3);
+        let counts = result.unsupported_counts();
+        assert_eq!(counts["USER-AGENT"], 1);
+        assert_eq!(counts["AND"], 1);
+        // What remains exportable is purely domain-based
+        assert_eq!(result.files.len(), 2);
+    }
+}
@@ -0,0 +1,66 @@
+//! Loon rule-set export
+//!
+//! Loon `.list` files share Surge's rule syntax, so supported rules are
+//! written in canonical form without a policy and with only `no-resolve`
+//! kept from their options.
+
+use crate::rule::{Rule, RuleEntry, RuleOptions};
+
+use super::{Export, ExportFile};
+
+/// True if Loon understands the rule, including every rule nested in a
+/// logical rule
+fn supported(rule: &Rule) -> bool {
+    match rule {
+        Rule::And(entries) | Rule::Or(entries) => entries.iter().all(|e| supported(&e.rule)),
+        Rule::Not(entry) => supported(&entry.rule),
+        Rule::ProcessName(_) | Rule::Other { .. } => false,
+        _ => true,
+    }
+}
+
+/// Export a rule set as a Loon rule list
+pub fn export(entries: &[RuleEntry]) -> Export {
+    let mut result = Export::default();
+    let mut content = String::new();
+
+    for entry in entries {
+        if !supported(&entry.rule) {
+            result.unsupported.push(entry.clone());
+            continue;
+        }
+        let line = RuleEntry {
+            rule: entry.rule.clone(),
+            policy: None,
+            options: RuleOptions {
+                no_resolve: entry.options.no_resolve,
+                ..RuleOptions::default()
+            },
+        };
+        content.push_str(&line.to_string());
+        content.push('\n');
+    }
+
+    result.files.push(ExportFile {
+        suffix: ".list",
+        content,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::RuleSet;
+
+    #[test]
+    fn test_policy_and_options_are_stripped() {
+        let text = "DOMAIN-SUFFIX,a.com,REJECT,extended-matching\nIP-CIDR,1.1.1.0/24,no-resolve\nAND,((DOMAIN,a.com),(DEST-PORT,443))\nPROCESS-NAME,Telegram\nOR,((DOMAIN,b.com),(SRC-IP,10.0.0.1))\n";
+        let result = export(&RuleSet::parse(text).entries);
+        assert_eq!(
+            result.files[0].content,
+            "DOMAIN-SUFFIX,a.com\nIP-CIDR,1.1.1.0/24,no-resolve\nAND,((DOMAIN,a.com),(DEST-PORT,443))\n"
+        );
+        assert_eq!(result.unsupported.len(), 2);
+    }
+}
@@ -0,0 +1,60 @@
+//! Rule-set exporters for other proxy clients
+//!
+//! Each exporter converts parsed Surge rules into one or more files in the
+//! target client's format and reports the rules it cannot express.
+
+use std::collections::BTreeMap;
+
+use crate::manifest::ExportTarget;
+use crate::rule::RuleEntry;
+
+pub mod clash;
+pub mod loon;
+pub mod quantumultx;
+pub mod singbox;
+
+/// A single file produced by an exporter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportFile {
+    /// Appended to the rule-set name to form the file name, e.g. `.yaml`
+    pub suffix: &'static str,
+    pub content: String,
+}
+
+/// Every file suffix the exporter for `target` may produce
+pub fn suffixes(target: ExportTarget) -> &'static [&'static str] {
+    match target {
+        ExportTarget::Clash => &[".yaml", ".domain.yaml", ".ipcidr.yaml"],
+        ExportTarget::Singbox => &[".json"],
+        ExportTarget::Quantumultx | ExportTarget::Loon => &[".list"],
+    }
+}
+
+/// Result of exporting one rule set
+#[derive(Debug, Clone, Default)]
+pub struct Export {
+    pub files: Vec<ExportFile>,
+    /// Rules the target cannot express, left out of every file
+    pub unsupported: Vec<RuleEntry>,
+}
+
+impl Export {
+    /// Number of unsupported rules per rule type
+    pub fn unsupported_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in &self.unsupported {
+            *counts.entry(entry.rule.type_name()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Suffixes `target` may produce that this export did not, whose files
+    /// from an earlier run are stale
+    pub fn stale_suffixes(&self, target: ExportTarget) -> Vec<&'static str> {
+        suffixes(target)
+            .iter()
+            .copied()
+            .filter(|suffix| self.files.iter().all(|file| file.suffix != *suffix))
+            .collect()
+    }
+}
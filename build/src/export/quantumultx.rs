@@ -0,0 +1,98 @@
+//! Quantumult X filter export
+//!
+//! Produces a `.list` filter resource. Quantumult X requires a policy on every
+//! filter line, so each rule carries [`POLICY_PLACEHOLDER`], which users
+//! replace through the resource's `force-policy` setting.
+
+use crate::rule::{Rule, RuleEntry};
+
+use super::{Export, ExportFile};
+
+/// Policy written on every line, overridden by `force-policy` when the
+/// filter is imported
+pub const POLICY_PLACEHOLDER: &str = "PROXY";
+
+/// Convert one rule to a filter line, or `None` if Quantumult X has no
+/// equivalent
+fn filter_line(entry: &RuleEntry) -> Option<String> {
+    let (rule_type, value) = match &entry.rule {
+        Rule::Domain(v) => ("HOST", v.clone()),
+        Rule::DomainSuffix(v) => ("HOST-SUFFIX", v.clone()),
+        Rule::DomainKeyword(v) => ("HOST-KEYWORD", v.clone()),
+        Rule::DomainWildcard(v) => ("HOST-WILDCARD", v.clone()),
+        Rule::IpCidr(net) => ("IP-CIDR", net.to_string()),
+        Rule::IpCidr6(net) => ("IP6-CIDR", net.to_string()),
+        Rule::IpAsn(asn) => ("IP-ASN", asn.to_string()),
+        Rule::Geoip(v) => ("GEOIP", v.clone()),
+        Rule::UserAgent(v) => ("USER-AGENT", v.clone()),
+        _ => return None,
+    };
+
+    let resolves = matches!(
+        entry.rule,
+        Rule::IpCidr(_) | Rule::IpCidr6(_) | Rule::IpAsn(_) | Rule::Geoip(_)
+    );
+    if resolves && entry.options.no_resolve {
+        Some(format!(
+            "{},{},{},no-resolve",
+            rule_type, value, POLICY_PLACEHOLDER
+        ))
+    } else {
+        Some(format!("{},{},{}", rule_type, value, POLICY_PLACEHOLDER))
+    }
+}
+
+/// Export a rule set as a Quantumult X filter resource
+pub fn export(entries: &[RuleEntry]) -> Export {
+    let mut result = Export::default();
+    let mut content = String::new();
+
+    for entry in entries {
+        match filter_line(entry) {
+            Some(line) => {
+                content.push_str(&line);
+                content.push('\n');
+            }
+            None => result.unsupported.push(entry.clone()),
+        }
+    }
+
+    result.files.push(ExportFile {
+        suffix: ".list",
+        content,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::RuleSet;
+
+    fn export_text(text: &str) -> Export {
+        export(&RuleSet::parse(text).entries)
+    }
+
+    #[test]
+    fn test_filter_mapping() {
+        let result = export_text(
+            "DOMAIN,a.com\nDOMAIN-SUFFIX,b.com\nDOMAIN-KEYWORD,c\nIP-CIDR,1.1.1.0/24,no-resolve\nIP-CIDR6,2001:db8::/32\n",
+        );
+        assert!(result.unsupported.is_empty());
+        assert_eq!(
+            result.files[0].content,
+            "HOST,a.com,PROXY\nHOST-SUFFIX,b.com,PROXY\nHOST-KEYWORD,c,PROXY\nIP-CIDR,1.1.1.0/24,PROXY,no-resolve\nIP6-CIDR,2001:db8::/32,PROXY\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_rules_are_reported() {
+        let result = export_text(
+            "DOMAIN,a.com,REJECT\nPROCESS-NAME,Telegram\nAND,((DOMAIN,a.com),(DEST-PORT,443))\n",
+        );
+        assert_eq!(result.files[0].content, "HOST,a.com,PROXY\n");
+        let counts = result.unsupported_counts();
+        assert_eq!(counts["PROCESS-NAME"], 1);
+        assert_eq!(counts["AND"], 1);
+    }
+}
@@ -0,0 +1,157 @@
+//! sing-box source rule-set export
+//!
+//! Produces the JSON source format (`version` 1 or 2). sing-box ANDs
+//! `process_name` and port fields with the domain/IP fields of the same
+//! headless rule, so each group is written as its own rule.
+
+use serde_json::{json, Map, Value};
+
+use crate::rule::{Rule, RuleEntry};
+
+use super::{Export, ExportFile};
+
+/// Field name and value for a rule that maps onto a single sing-box field
+fn field(rule: &Rule) -> Option<(&'static str, Value)> {
+    Some(match rule {
+        Rule::Domain(v) => ("domain", json!(v)),
+        Rule::DomainSuffix(v) => ("domain_suffix", json!(v)),
+        Rule::DomainKeyword(v) => ("domain_keyword", json!(v)),
+        Rule::IpCidr(net) => ("ip_cidr", json!(net.to_string())),
+        Rule::IpCidr6(net) => ("ip_cidr", json!(net.to_string())),
+        Rule::ProcessName(v) => ("process_name", json!(v)),
+        Rule::DestPort(v) => match v.split_once('-') {
+            Some((lo, hi)) => ("port_range", json!(format!("{}:{}", lo, hi))),
+            None => ("port", json!(v.parse::<u16>().ok()?)),
+        },
+        _ => return None,
+    })
+}
+
+/// Convert one rule into a standalone headless rule object
+fn rule_object(entry: &RuleEntry) -> Option<Value> {
+    match &entry.rule {
+        Rule::And(entries) | Rule::Or(entries) => {
+            let mode = if matches!(entry.rule, Rule::And(_)) {
+                "and"
+            } else {
+                "or"
+            };
+            let rules: Option<Vec<Value>> = entries.iter().map(rule_object).collect();
+            Some(json!({ "type": "logical", "mode": mode, "rules": rules? }))
+        }
+        Rule::Not(inner) => {
+            let mut object = rule_object(inner)?;
+            object["invert"] = json!(true);
+            Some(object)
+        }
+        rule => {
+            let (name, value) = field(rule)?;
+            Some(json!({ name: [value] }))
+        }
+    }
+}
+
+/// Export a rule set as a sing-box source rule-set
+pub fn export(entries: &[RuleEntry], version: u8) -> Export {
+    let mut result = Export::default();
+
+    // Fields grouped so that each group becomes one OR-matching rule
+    let mut matchers: Map<String, Value> = Map::new();
+    let mut processes: Map<String, Value> = Map::new();
+    let mut ports: Map<String, Value> = Map::new();
+    let mut logical = Vec::new();
+
+    for entry in entries {
+        if let Some((name, value)) = field(&entry.rule) {
+            let group = match name {
+                "process_name" => &mut processes,
+                "port" | "port_range" => &mut ports,
+                _ => &mut matchers,
+            };
+            group
+                .entry(name)
+                .or_insert_with(|| json!([]))
+                .as_array_mut()
+                .expect("field values are arrays")
+                .push(value);
+        } else if let Some(object) = rule_object(entry) {
+            logical.push(object);
+        } else {
+            result.unsupported.push(entry.clone());
+        }
+    }
+
+    let mut rules: Vec<Value> = [matchers, processes, ports]
+        .into_iter()
+        .filter(|group| !group.is_empty())
+        .map(Value::Object)
+        .collect();
+    rules.extend(logical);
+
+    let document = json!({ "version": version, "rules": rules });
+    let mut content = serde_json::to_string_pretty(&document).expect("JSON values serialize");
+    content.push('\n');
+
+    result.files.push(ExportFile {
+        suffix: ".json",
+        content,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::RuleSet;
+
+    fn export_json(text: &str) -> (Value, Export) {
+        let result = export(&RuleSet::parse(text).entries, 2);
+        let value = serde_json::from_str(&result.files[0].content).unwrap();
+        (value, result)
+    }
+
+    #[test]
+    fn test_field_mapping() {
+        let (value, result) = export_json(
+            "DOMAIN,a.com\nDOMAIN-SUFFIX,b.com\nDOMAIN-KEYWORD,c\nIP-CIDR,1.1.1.0/24,no-resolve\nIP-CIDR6,2001:db8::/32\nPROCESS-NAME,Telegram\n",
+        );
+        assert!(result.unsupported.is_empty());
+        assert_eq!(
+            value,
+            json!({
+                "version": 2,
+                "rules": [
+                    {
+                        "domain": ["a.com"],
+                        "domain_suffix": ["b.com"],
+                        "domain_keyword": ["c"],
+                        "ip_cidr": ["1.1.1.0/24", "2001:db8::/32"]
+                    },
+                    { "process_name": ["Telegram"] }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_logical_and_unsupported_rules() {
+        let (value, result) = export_json(
+            "AND,((DOMAIN,a.com),(NOT,((DEST-PORT,443))))\nUSER-AGENT,*bili*\nGEOIP,CN\n",
+        );
+        assert_eq!(
+            value["rules"][0],
+            json!({
+                "type": "logical",
+                "mode": "and",
+                "rules": [{ "domain": ["a.com"] }, { "port": [443], "invert": true }]
+            })
+        );
+        assert_eq!(result.unsupported.len(), 2);
+    }
+
+    #[test]
+    fn test_version_field() {
+        let result = export(&RuleSet::parse("DOMAIN,a.com\n").entries, 1);
+        assert!(result.files[0].content.contains("\"version\": 1"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Safety checks against truncated upstream lists
+//!
+//! An upstream that briefly serves an empty or cut-off list would otherwise
+//! replace a good rule set. Each check fails with a [`GuardError`] so the
+//! caller keeps the existing file and reports the source as failed.
+
+use std::fmt;
+
+use crate::manifest::SourceOptions;
+
+/// Why a sync was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardError {
+    /// The new set has fewer rules than `min_entries`
+    TooFewEntries { count: usize, min: usize },
+    /// The new set lost a larger share of the existing file than `max_shrink`
+    Shrunk {
+        old: usize,
+        new: usize,
+        max_percent: u8,
+    },
+    /// An upstream list lacks its end-of-file sentinel line
+    MissingSentinel { sentinel: String },
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardError::TooFewEntries { count, min } => write!(
+                f,
+                "only {} rules, expected at least {}; keeping the existing file",
+                count, min
+            ),
+            GuardError::Shrunk {
+                old,
+                new,
+                max_percent,
+            } => write!(
+                f,
+                "rules would drop from {} to {} ({}%), more than the allowed {}%; keeping the existing file",
+                old,
+                new,
+                shrink_percent(*old, *new),
+                max_percent
+            ),
+            GuardError::MissingSentinel { sentinel } => write!(
+                f,
+                "upstream list lacks its `{}` line and may be truncated; keeping the existing file",
+                sentinel
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+/// Share of `old` rules lost when going down to `new`, rounded down
+fn shrink_percent(old: usize, new: usize) -> usize {
+    if old == 0 {
+        return 0;
+    }
+    old.saturating_sub(new) * 100 / old
+}
+
+/// Check a new rule count against the source's `min_entries` and, when a
+/// file already exists, its `max_shrink`
+pub fn check_counts(
+    options: &SourceOptions,
+    old: Option<usize>,
+    new: usize,
+) -> Result<(), GuardError> {
+    if new < options.min_entries {
+        return Err(GuardError::TooFewEntries {
+            count: new,
+            min: options.min_entries,
+        });
+    }
+    if let Some(old) = old {
+        let max_percent = options.max_shrink.0;
+        if shrink_percent(old, new) > usize::from(max_percent) {
+            return Err(GuardError::Shrunk {
+                old,
+                new,
+                max_percent,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check that `content` has a line reading `sentinel`, ignoring surrounding
+/// whitespace
+pub fn check_sentinel(sentinel: &str, content: &str) -> Result<(), GuardError> {
+    if content.lines().any(|line| line.trim() == sentinel.trim()) {
+        Ok(())
+    } else {
+        Err(GuardError::MissingSentinel {
+            sentinel: sentinel.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ShrinkLimit;
+
+    #[test]
+    fn test_check_counts() {
+        let options = SourceOptions::default();
+        assert!(check_counts(&options, None, 1).is_ok());
+        assert_eq!(
+            check_counts(&options, None, 0),
+            Err(GuardError::TooFewEntries { count: 0, min: 1 })
+        );
+        assert!(check_counts(&options, Some(100), 50).is_ok());
+        assert_eq!(
+            check_counts(&options, Some(100), 49),
+            Err(GuardError::Shrunk {
+                old: 100,
+                new: 49,
+                max_percent: 50
+            })
+        );
+
+        let options = SourceOptions {
+            min_entries: 0,
+            max_shrink: ShrinkLimit(100),
+            ..SourceOptions::default()
+        };
+        assert!(check_counts(&options, Some(100), 0).is_ok());
+    }
+
+    #[test]
+    fn test_check_counts_empty_set() {
+        let options = SourceOptions {
+            min_entries: 0,
+            ..SourceOptions::default()
+        };
+        assert!(check_counts(&options, None, 0).is_ok());
+        assert!(check_counts(&options, Some(0), 0).is_ok());
+    }
+
+    #[test]
+    fn test_check_sentinel() {
+        let eof = "################## EOF ##################";
+        let content = format!("DOMAIN,a.com\n{}\n", eof);
+        assert!(check_sentinel(eof, &content).is_ok());
+        assert!(matches!(
+            check_sentinel(eof, "DOMAIN,a.com\nDOMAIN-SUF"),
+            Err(GuardError::MissingSentinel { .. })
+        ));
+    }
+}
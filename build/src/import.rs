@@ -0,0 +1,297 @@
+//! Conversion of non-Surge rule lists
+//!
+//! Turns Clash rule-provider payloads, AdGuard / Adblock Plus filters, hosts
+//! files and bare domain lists into Surge rule text. Lines with no Surge
+//! equivalent are reported as [`Skipped`] instead of being guessed at.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+use crate::manifest::InputFormat;
+use crate::rule::{ParseError, RuleEntry};
+
+/// A line that could not be converted, with its 1-based line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub line: usize,
+    pub text: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {} (`{}`)", self.line, self.reason, self.text)
+    }
+}
+
+/// Surge rule text produced from an upstream list
+#[derive(Debug, Clone, Default)]
+pub struct Conversion {
+    pub text: String,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Host names that hosts files map for the local machine itself
+const LOCAL_HOSTS: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-allhosts",
+    "0.0.0.0",
+];
+
+/// True for a plain host name such as `ads.example.com`
+fn is_domain(value: &str) -> bool {
+    !value.is_empty()
+        && !value.starts_with('.')
+        && !value.ends_with('.')
+        && !value.contains("..")
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+}
+
+/// Convert one domain entry in the shared `+.`/`.` prefix notation
+fn domain_rule(value: &str) -> Result<String, &'static str> {
+    if let Some(suffix) = value.strip_prefix("+.") {
+        if is_domain(suffix) {
+            return Ok(format!("DOMAIN-SUFFIX,{}", suffix));
+        }
+    } else if let Some(suffix) = value.strip_prefix('.') {
+        if is_domain(suffix) {
+            return Ok(format!("DOMAIN-SUFFIX,{}", suffix));
+        }
+    } else if value.contains('*') {
+        return Err("wildcard pattern has no exact Surge equivalent");
+    } else if is_domain(value) {
+        return Ok(format!("DOMAIN,{}", value));
+    }
+    Err("not a domain")
+}
+
+/// Convert an IP address or CIDR range
+fn cidr_rule(value: &str) -> Option<String> {
+    let net = match value.parse::<IpNet>() {
+        Ok(net) => net,
+        Err(_) => IpNet::from(value.parse::<IpAddr>().ok()?),
+    };
+    Some(match net {
+        IpNet::V4(net) => format!("IP-CIDR,{}", net),
+        IpNet::V6(net) => format!("IP-CIDR6,{}", net),
+    })
+}
+
+/// Convert one item of a Clash `payload:` list, in any provider behavior
+fn clash_item(value: &str) -> Result<String, &'static str> {
+    if value.contains(',') {
+        let line = match value.split_once(',') {
+            Some(("DST-PORT", rest)) => format!("DEST-PORT,{}", rest),
+            _ => value.to_string(),
+        };
+        return match line.parse::<RuleEntry>() {
+            Err(ParseError::UnsupportedType(_)) => Err("rule type has no Surge equivalent"),
+            // Anything else is left for the Surge parser to judge
+            _ => Ok(line),
+        };
+    }
+    if let Some(rule) = cidr_rule(value) {
+        return Ok(rule);
+    }
+    // In a Clash domain set a leading dot matches subdomains only
+    if let Some(suffix) = value.strip_prefix('.') {
+        if is_domain(suffix) {
+            return Ok(format!("DOMAIN-WILDCARD,*.{}", suffix));
+        }
+    }
+    domain_rule(value)
+}
+
+fn convert_clash(line: &str) -> Option<Result<String, &'static str>> {
+    let line = line.trim();
+    if line.starts_with('#') || line == "payload:" {
+        return None;
+    }
+    let Some(item) = line.strip_prefix('-') else {
+        return Some(Err("not a payload item"));
+    };
+    let item = item.split(" #").next().unwrap_or_default().trim();
+    let item = item
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .or_else(|| item.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+        .unwrap_or(item);
+    Some(clash_item(item))
+}
+
+fn convert_adguard(line: &str) -> Option<Result<String, &'static str>> {
+    let line = line.trim();
+    if line.starts_with('!') || (line.starts_with('[') && line.ends_with(']')) {
+        return None;
+    }
+    if ["##", "#@#", "#?#", "#$#", "#%#"]
+        .iter()
+        .any(|marker| line.contains(marker))
+    {
+        return Some(Err("cosmetic filter"));
+    }
+    if line.starts_with('#') {
+        return None;
+    }
+    if line.starts_with("@@") {
+        return Some(Err("exception rule"));
+    }
+    // AdGuard also accepts hosts-file syntax
+    if line.split_whitespace().count() > 1 {
+        return Some(convert_hosts_line(line).unwrap_or(Err("not a network filter")));
+    }
+    let Some(pattern) = line.strip_prefix("||") else {
+        return Some(Err("not anchored to a domain"));
+    };
+    if pattern.contains('$') {
+        return Some(Err("filter modifiers"));
+    }
+    let domain = pattern.strip_suffix('^').unwrap_or(pattern);
+    if domain.contains('/') {
+        return Some(Err("path-based rule"));
+    }
+    if domain.contains('*') {
+        return Some(Err("wildcard pattern has no exact Surge equivalent"));
+    }
+    if !is_domain(domain) {
+        return Some(Err("not a domain"));
+    }
+    Some(Ok(format!("DOMAIN-SUFFIX,{}", domain)))
+}
+
+/// Convert a hosts line that may map several names, one rule per name
+fn convert_hosts_line(line: &str) -> Option<Result<String, &'static str>> {
+    let line = line.split('#').next().unwrap_or_default().trim();
+    if line.is_empty() {
+        return None;
+    }
+    let mut fields = line.split_whitespace();
+    if fields.next()?.parse::<IpAddr>().is_err() {
+        return Some(Err("not a hosts entry"));
+    }
+    let mut rules = Vec::new();
+    for host in fields.filter(|host| !LOCAL_HOSTS.contains(host)) {
+        if !is_domain(host) {
+            return Some(Err("not a domain"));
+        }
+        rules.push(format!("DOMAIN,{}", host));
+    }
+    if rules.is_empty() {
+        return None;
+    }
+    Some(Ok(rules.join("\n")))
+}
+
+fn convert_domains(line: &str) -> Option<Result<String, &'static str>> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    Some(domain_rule(line))
+}
+
+/// Convert an upstream list to Surge rule text. Comments and blank lines are
+/// dropped; Surge input is returned unchanged.
+pub fn convert(format: InputFormat, content: &str) -> Conversion {
+    let convert_line = match format {
+        InputFormat::Surge => {
+            return Conversion {
+                text: content.to_string(),
+                skipped: Vec::new(),
+            }
+        }
+        InputFormat::Clash => convert_clash,
+        InputFormat::Adguard => convert_adguard,
+        InputFormat::Hosts => convert_hosts_line,
+        InputFormat::Domains => convert_domains,
+    };
+
+    let mut conversion = Conversion::default();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match convert_line(line) {
+            None => {}
+            Some(Ok(rules)) => {
+                conversion.text.push_str(&rules);
+                conversion.text.push('\n');
+            }
+            Some(Err(reason)) => conversion.skipped.push(Skipped {
+                line: i + 1,
+                text: line.trim().to_string(),
+                reason,
+            }),
+        }
+    }
+    conversion
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reasons(conversion: &Conversion) -> Vec<&'static str> {
+        conversion.skipped.iter().map(|s| s.reason).collect()
+    }
+
+    #[test]
+    fn test_clash_payloads() {
+        let text = "payload:\n  - 'DOMAIN-SUFFIX,a.com'\n  - \"DST-PORT,443\"\n  - GEOSITE,cn\n  - '+.b.com'\n  - '.c.com'\n  - d.com\n  - '10.0.0.0/8'\n  - '2001:db8::1'\n";
+        let conversion = convert(InputFormat::Clash, text);
+        assert_eq!(
+            conversion.text,
+            "DOMAIN-SUFFIX,a.com\nDEST-PORT,443\nDOMAIN-SUFFIX,b.com\nDOMAIN-WILDCARD,*.c.com\nDOMAIN,d.com\nIP-CIDR,10.0.0.0/8\nIP-CIDR6,2001:db8::1/128\n"
+        );
+        assert_eq!(conversion.skipped.len(), 1);
+        assert_eq!(conversion.skipped[0].line, 4);
+    }
+
+    #[test]
+    fn test_adguard_filters() {
+        let text = "[Adblock Plus 2.0]\n! Title: test\n||ads.example.com^\n||tracker.net^$third-party\n@@||good.com^\nexample.com##.banner\n||cdn.com/ads/*\n0.0.0.0 hosts.example\n/banner/\n";
+        let conversion = convert(InputFormat::Adguard, text);
+        assert_eq!(
+            conversion.text,
+            "DOMAIN-SUFFIX,ads.example.com\nDOMAIN,hosts.example\n"
+        );
+        assert_eq!(
+            reasons(&conversion),
+            vec![
+                "filter modifiers",
+                "exception rule",
+                "cosmetic filter",
+                "path-based rule",
+                "not anchored to a domain"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hosts_and_domain_lists() {
+        let text = "# hosts\n127.0.0.1 localhost\n0.0.0.0 a.com b.com # ads\n::1 ip6-localhost\nbad line\n";
+        let conversion = convert(InputFormat::Hosts, text);
+        assert_eq!(conversion.text, "DOMAIN,a.com\nDOMAIN,b.com\n");
+        assert_eq!(reasons(&conversion), vec!["not a hosts entry"]);
+
+        let conversion = convert(InputFormat::Domains, "a.com\n.b.com\n+.c.com\n*.d.com\n");
+        assert_eq!(
+            conversion.text,
+            "DOMAIN,a.com\nDOMAIN-SUFFIX,b.com\nDOMAIN-SUFFIX,c.com\n"
+        );
+        assert_eq!(conversion.skipped.len(), 1);
+    }
+}
@@ -1,9 +1,25 @@
 //! Common utilities for Surge sync tools
 //!
-//! Provides logging utilities with Cargo-style output and color support for GitHub Actions.
+//! Provides logging utilities with Cargo-style output and color support for GitHub Actions,
+//! plus the download, manifest, parsing and export modules shared by the sync tools.
 
 use std::time::Instant;
 
+pub mod audit;
+pub mod cache;
+pub mod changelog;
+pub mod diff;
+pub mod download;
+pub mod export;
+pub mod guard;
+pub mod import;
+pub mod manifest;
+pub mod module;
+pub mod optimize;
+pub mod report;
+pub mod rule;
+pub mod vendor;
+
 /// ANSI color codes for terminal output
 pub mod colors {
     pub const GREEN: &str = "\x1b[32m";
@@ -82,41 +98,6 @@ impl Timer {
     }
 }
 
-/// Download content from a URL with error handling
-///
-/// # Arguments
-/// * `url` - The URL to download from
-///
-/// # Returns
-/// * `Ok(Vec<u8>)` - The downloaded content as bytes
-/// * `Err` - If the download fails
-pub fn download_url(url: &str) -> anyhow::Result<Vec<u8>> {
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-
-    let response = client.get(url).send()?;
-
-    if !response.status().is_success() {
-        anyhow::bail!("HTTP {} for {}", response.status(), url);
-    }
-
-    Ok(response.bytes()?.to_vec())
-}
-
-/// Download text content from a URL
-///
-/// # Arguments
-/// * `url` - The URL to download from
-///
-/// # Returns
-/// * `Ok(String)` - The downloaded content as text
-/// * `Err` - If the download fails
-pub fn download_text(url: &str) -> anyhow::Result<String> {
-    let bytes = download_url(url)?;
-    Ok(String::from_utf8(bytes)?)
-}
-
 /// Convert a name to lowercase camelCase format
 ///
 /// # Arguments
@@ -156,13 +137,16 @@ pub fn ensure_dir(path: &std::path::Path) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Compare two text contents ignoring "# Last Updated:" lines.
+/// True for header lines that change on every run without the content
+/// changing ("# Last Updated:")
+pub fn is_volatile_line(line: &str) -> bool {
+    line.trim().starts_with("# Last Updated:")
+}
+
+/// Compare two text contents ignoring volatile header lines.
 /// Returns true if meaningful content has changed.
 pub fn has_text_changed(new_content: &str, existing_content: &str) -> bool {
-    let filter = |line: &&str| {
-        let trimmed = line.trim();
-        !trimmed.starts_with("# Last Updated:")
-    };
+    let filter = |line: &&str| !is_volatile_line(line);
     let new_lines: Vec<&str> = new_content.lines().filter(filter).collect();
     let old_lines: Vec<&str> = existing_content.lines().filter(filter).collect();
     new_lines != old_lines
@@ -203,6 +187,13 @@ mod tests {
         assert!(has_text_changed(new, old));
     }
 
+    #[test]
+    fn test_has_text_changed_records_serving_mirror() {
+        let old = "# test\n# Last Updated: 2026-02-24\n# Upstream: https://a.com/x.conf\nRULE1\n";
+        let new = "# test\n# Last Updated: 2026-02-25\n# Upstream: https://a.com/x.conf\n# Served By: https://b.com/x.conf\nRULE1\n";
+        assert!(has_text_changed(new, old));
+    }
+
     #[test]
     fn test_has_text_changed_new_file() {
         let new = "# test\n# Last Updated: 2026-02-25\nRULE1\n";
@@ -0,0 +1,1344 @@
+//! Declarative source manifest
+//!
+//! Loads and validates `sources.toml`, the single list of upstream sources
+//! shared by every sync tool.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::download::{cdn_mirrors, check_proxy, check_user_agent, ClientConfig, DEFAULT_RETRIES};
+use crate::module::{Patch, SectionKind};
+use crate::rule::RuleEntry;
+
+/// Default manifest file name, relative to the project root
+pub const MANIFEST_FILE: &str = "sources.toml";
+
+/// Kind of resource a source produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Rule,
+    Module,
+    Icon,
+    Geoip,
+}
+
+impl SourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceKind::Rule => "rule",
+            SourceKind::Module => "module",
+            SourceKind::Icon => "icon",
+            SourceKind::Geoip => "geoip",
+        }
+    }
+
+    /// Categories (output subdirectories) accepted for this kind
+    pub fn categories(&self) -> &'static [&'static str] {
+        match self {
+            SourceKind::Rule => &[
+                "adblock", "ai", "apple", "media", "social", "gaming", "proxy",
+            ],
+            SourceKind::Module => &["enhance", "adblock", "utility", "subtitle"],
+            SourceKind::Icon => &["apps", "country", "policy"],
+            SourceKind::Geoip => &[],
+        }
+    }
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What to do with rule lines Surge would refuse to load
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidLinePolicy {
+    /// Drop the offending lines and emit a warning annotation for each
+    #[default]
+    Drop,
+    /// Fail the source and keep the existing file
+    Fail,
+}
+
+/// Syntax of a rule source's upstream lists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// Surge rule lines, used as-is
+    #[default]
+    Surge,
+    /// Clash rule-provider `payload:` YAML in any behavior
+    Clash,
+    /// AdGuard / Adblock Plus network filters such as `||example.com^`
+    #[serde(alias = "abp")]
+    Adguard,
+    /// Hosts file entries such as `0.0.0.0 ads.example.com`
+    Hosts,
+    /// One domain per line, `.example.com` for a suffix
+    Domains,
+}
+
+impl InputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InputFormat::Surge => "surge",
+            InputFormat::Clash => "clash",
+            InputFormat::Adguard => "adguard",
+            InputFormat::Hosts => "hosts",
+            InputFormat::Domains => "domains",
+        }
+    }
+}
+
+/// Additional rule formats written alongside the Surge rule set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportTarget {
+    /// Clash / mihomo rule-provider YAML
+    Clash,
+    /// sing-box source rule-set JSON, written to `rules-singbox/`
+    Singbox,
+    /// Quantumult X filter list, written to `rules-quantumultx/`
+    Quantumultx,
+    /// Loon rule list, written to `rules-loon/`
+    Loon,
+}
+
+impl ExportTarget {
+    pub const ALL: [ExportTarget; 4] = [
+        ExportTarget::Clash,
+        ExportTarget::Singbox,
+        ExportTarget::Quantumultx,
+        ExportTarget::Loon,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportTarget::Clash => "clash",
+            ExportTarget::Singbox => "singbox",
+            ExportTarget::Quantumultx => "quantumultx",
+            ExportTarget::Loon => "loon",
+        }
+    }
+}
+
+/// sing-box rule-set format version (1 or 2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u8")]
+pub struct SingboxVersion(pub u8);
+
+impl Default for SingboxVersion {
+    fn default() -> Self {
+        Self(2)
+    }
+}
+
+impl TryFrom<u8> for SingboxVersion {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 | 2 => Ok(Self(version)),
+            other => Err(format!(
+                "unsupported sing-box rule-set version {}, expected 1 or 2",
+                other
+            )),
+        }
+    }
+}
+
+/// Largest share of a rule set, in percent, one sync may remove
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u8")]
+pub struct ShrinkLimit(pub u8);
+
+impl Default for ShrinkLimit {
+    fn default() -> Self {
+        Self(50)
+    }
+}
+
+impl TryFrom<u8> for ShrinkLimit {
+    type Error = String;
+
+    fn try_from(percent: u8) -> Result<Self, Self::Error> {
+        if percent <= 100 {
+            Ok(Self(percent))
+        } else {
+            Err(format!("max_shrink is a percentage, got {}", percent))
+        }
+    }
+}
+
+/// How a module source's argument overrides are applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentMode {
+    /// Rewrite the `#!arguments` defaults; users can still change them in Surge
+    #[default]
+    Defaults,
+    /// Substitute every `{{{name}}}` placeholder and drop `#!arguments`
+    Render,
+}
+
+impl ArgumentMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArgumentMode::Defaults => "defaults",
+            ArgumentMode::Render => "render",
+        }
+    }
+}
+
+/// Optional per-source settings
+#[derive(Debug, Clone)]
+pub struct SourceOptions {
+    /// Set to false to keep the entry in the manifest without syncing it
+    pub enabled: bool,
+
+    /// Retries after a transient download failure
+    pub retries: u32,
+
+    /// Fall back to public CDN copies of GitHub-hosted URLs
+    pub cdn_mirrors: bool,
+
+    /// Syntax of the upstream lists (rule sources only)
+    pub format: InputFormat,
+
+    /// Handling of invalid rule lines (rule sources only)
+    pub on_invalid: InvalidLinePolicy,
+
+    /// Remove redundant rules and merge IP ranges (rule sources only)
+    pub optimize: bool,
+
+    /// Other client formats to export (rule sources only)
+    pub exports: Vec<ExportTarget>,
+
+    /// Format version for the `singbox` export
+    pub singbox_version: SingboxVersion,
+
+    /// Fewest rules a sync may produce (rule sources only)
+    pub min_entries: usize,
+
+    /// Largest share of the existing rules a sync may remove (rule sources only)
+    pub max_shrink: ShrinkLimit,
+
+    /// Line every upstream list must contain, such as an EOF trailer
+    /// (rule sources only)
+    pub eof_sentinel: Option<String>,
+
+    /// Copy remote `script-path` scripts into the repository and point the
+    /// module at the copies (module sources only)
+    pub vendor_scripts: bool,
+
+    /// Values for arguments the module declares in `#!arguments`
+    /// (module sources only)
+    pub arguments: BTreeMap<String, String>,
+
+    /// How `arguments` are applied (module sources only)
+    pub arguments_mode: ArgumentMode,
+}
+
+impl Default for SourceOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retries: DEFAULT_RETRIES,
+            cdn_mirrors: false,
+            format: InputFormat::default(),
+            on_invalid: InvalidLinePolicy::default(),
+            optimize: false,
+            exports: Vec::new(),
+            singbox_version: SingboxVersion::default(),
+            min_entries: 1,
+            max_shrink: ShrinkLimit::default(),
+            eof_sentinel: None,
+            vendor_scripts: false,
+            arguments: BTreeMap::new(),
+            arguments_mode: ArgumentMode::default(),
+        }
+    }
+}
+
+/// Where a source's content comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// An upstream http(s) URL
+    Remote(String),
+    /// A file relative to the `rules/` directory
+    Local(PathBuf),
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Remote(url) => f.write_str(url),
+            Input::Local(path) => write!(f, "rules/{}", path.display()),
+        }
+    }
+}
+
+/// A validated upstream source
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub name: String,
+    pub kind: SourceKind,
+    /// Output subdirectory; `None` for kinds without categories
+    pub category: Option<String>,
+    /// One remote input for plain sources, several for composite rule sets
+    pub inputs: Vec<Input>,
+    /// Fallback URLs for a single-`url` source, tried in order
+    pub mirrors: Vec<String>,
+    /// Expected lowercase hex SHA-256 of a single-`url` source's content
+    pub sha256: Option<String>,
+    /// Rules removed from a composite rule set after merging
+    pub exclude: Vec<RuleEntry>,
+    /// Edits applied to a module after download
+    pub patch: Option<Patch>,
+    pub options: SourceOptions,
+    /// Line of the `[[source]]` entry in the manifest
+    pub line: usize,
+}
+
+impl Source {
+    /// The upstream URL of a single-input source, or the first remote input
+    pub fn url(&self) -> &str {
+        self.inputs
+            .iter()
+            .find_map(|input| match input {
+                Input::Remote(url) => Some(url.as_str()),
+                Input::Local(_) => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Fallback URLs for the remote input `url`: the declared mirrors, then
+    /// CDN rewrites when `cdn_mirrors` is set
+    pub fn mirrors_for(&self, url: &str) -> Vec<String> {
+        let mut mirrors = if url == self.url() {
+            self.mirrors.clone()
+        } else {
+            Vec::new()
+        };
+        if self.options.cdn_mirrors {
+            for mirror in cdn_mirrors(url) {
+                if !mirrors.contains(&mirror) {
+                    mirrors.push(mirror);
+                }
+            }
+        }
+        mirrors
+    }
+
+    /// True when the source merges several inputs or applies exclusions
+    pub fn is_composite(&self) -> bool {
+        self.inputs.len() > 1 || !self.exclude.is_empty()
+    }
+}
+
+/// The full set of sources declared in the manifest
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    pub sources: Vec<Source>,
+    /// HTTP client settings from the `[http]` table; `ca_certs` paths are
+    /// relative to the project root
+    pub http: ClientConfig,
+    /// Raw URL of this repository's root, where vendored module scripts are
+    /// served, from the `[modules]` table; without a trailing slash
+    pub raw_base_url: Option<String>,
+}
+
+/// Manifest loading or validation failure with its location
+#[derive(Debug)]
+pub struct ManifestError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    #[serde(default)]
+    http: RawHttp,
+    #[serde(default)]
+    modules: RawModules,
+    #[serde(default)]
+    source: Vec<Spanned<RawSource>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawModules {
+    raw_base_url: Option<Spanned<String>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHttp {
+    proxy: Option<Spanned<String>>,
+    #[serde(default)]
+    ca_certs: Vec<PathBuf>,
+    user_agent: Option<Spanned<String>>,
+    timeout: Option<Spanned<u64>>,
+    connect_timeout: Option<Spanned<u64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSource {
+    name: Spanned<String>,
+    kind: Spanned<SourceKind>,
+    category: Option<Spanned<String>>,
+    url: Option<Spanned<String>>,
+    inputs: Option<Spanned<Vec<Spanned<String>>>>,
+    #[serde(default)]
+    mirrors: Vec<Spanned<String>>,
+    sha256: Option<Spanned<String>>,
+    #[serde(default)]
+    exclude: Vec<Spanned<String>>,
+    patch: Option<Spanned<RawPatch>>,
+    #[serde(default)]
+    options: RawOptions,
+}
+
+/// `options` as written, each value with its location so options that do
+/// not apply to the source's kind can be reported
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOptions {
+    enabled: Option<bool>,
+    retries: Option<u32>,
+    cdn_mirrors: Option<bool>,
+    format: Option<Spanned<InputFormat>>,
+    on_invalid: Option<Spanned<InvalidLinePolicy>>,
+    optimize: Option<Spanned<bool>>,
+    exports: Option<Spanned<Vec<ExportTarget>>>,
+    singbox_version: Option<Spanned<SingboxVersion>>,
+    min_entries: Option<Spanned<usize>>,
+    max_shrink: Option<Spanned<ShrinkLimit>>,
+    eof_sentinel: Option<Spanned<String>>,
+    vendor_scripts: Option<Spanned<bool>>,
+    arguments: Option<Spanned<BTreeMap<String, String>>>,
+    arguments_mode: Option<Spanned<ArgumentMode>>,
+}
+
+impl RawOptions {
+    /// Location of each set option that only one kind of source supports
+    fn kind_specific(&self) -> Vec<(SourceKind, &'static str, Range<usize>)> {
+        let span = |value: Option<Range<usize>>, kind, name| value.map(|span| (kind, name, span));
+        [
+            span(
+                self.format.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "format",
+            ),
+            span(
+                self.on_invalid.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "on_invalid",
+            ),
+            span(
+                self.optimize.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "optimize",
+            ),
+            span(
+                self.exports.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "exports",
+            ),
+            span(
+                self.singbox_version.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "singbox_version",
+            ),
+            span(
+                self.min_entries.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "min_entries",
+            ),
+            span(
+                self.max_shrink.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "max_shrink",
+            ),
+            span(
+                self.eof_sentinel.as_ref().map(Spanned::span),
+                SourceKind::Rule,
+                "eof_sentinel",
+            ),
+            span(
+                self.vendor_scripts.as_ref().map(Spanned::span),
+                SourceKind::Module,
+                "vendor_scripts",
+            ),
+            span(
+                self.arguments.as_ref().map(Spanned::span),
+                SourceKind::Module,
+                "arguments",
+            ),
+            span(
+                self.arguments_mode.as_ref().map(Spanned::span),
+                SourceKind::Module,
+                "arguments_mode",
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// The options with every unset value at its default
+    fn into_options(self) -> SourceOptions {
+        let defaults = SourceOptions::default();
+        SourceOptions {
+            enabled: self.enabled.unwrap_or(defaults.enabled),
+            retries: self.retries.unwrap_or(defaults.retries),
+            cdn_mirrors: self.cdn_mirrors.unwrap_or(defaults.cdn_mirrors),
+            format: self.format.map_or(defaults.format, Spanned::into_inner),
+            on_invalid: self
+                .on_invalid
+                .map_or(defaults.on_invalid, Spanned::into_inner),
+            optimize: self.optimize.map_or(defaults.optimize, Spanned::into_inner),
+            exports: self.exports.map_or(defaults.exports, Spanned::into_inner),
+            singbox_version: self
+                .singbox_version
+                .map_or(defaults.singbox_version, Spanned::into_inner),
+            min_entries: self
+                .min_entries
+                .map_or(defaults.min_entries, Spanned::into_inner),
+            max_shrink: self
+                .max_shrink
+                .map_or(defaults.max_shrink, Spanned::into_inner),
+            eof_sentinel: self.eof_sentinel.map(Spanned::into_inner),
+            vendor_scripts: self
+                .vendor_scripts
+                .map_or(defaults.vendor_scripts, Spanned::into_inner),
+            arguments: self
+                .arguments
+                .map_or(defaults.arguments, Spanned::into_inner),
+            arguments_mode: self
+                .arguments_mode
+                .map_or(defaults.arguments_mode, Spanned::into_inner),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPatch {
+    name: Option<String>,
+    desc: Option<String>,
+    /// Section name to patterns
+    #[serde(default)]
+    remove: BTreeMap<String, Vec<Spanned<String>>>,
+    /// Section name to lines
+    #[serde(default)]
+    append: BTreeMap<String, Vec<Spanned<String>>>,
+}
+
+/// Check that `url` is a well-formed http(s) URL
+fn check_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        Ok(parsed) => Err(format!(
+            "unsupported URL scheme `{}` in `{}`",
+            parsed.scheme(),
+            url
+        )),
+        Err(e) => Err(format!("malformed URL `{}`: {}", url, e)),
+    }
+}
+
+/// Parse a composite input: an http(s) URL or a relative path under `rules/`
+fn parse_input(value: &str) -> Result<Input, String> {
+    if value.contains("://") {
+        return check_url(value).map(|_| Input::Remote(value.to_string()));
+    }
+    let path = Path::new(value);
+    let relative = path
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)));
+    if value.is_empty() || !relative {
+        return Err(format!(
+            "local input `{}` must be a relative path under rules/",
+            value
+        ));
+    }
+    Ok(Input::Local(path.to_path_buf()))
+}
+
+/// Convert a byte offset into a 1-based (line, column) pair
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |s| s.chars().count()) + 1;
+    (line, column)
+}
+
+impl Manifest {
+    /// Load and validate the manifest at `path`
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ManifestError {
+            path: Some(path.to_path_buf()),
+            line: 0,
+            column: 0,
+            message: e.to_string(),
+        })?;
+        Self::parse(&text).map_err(|mut e| {
+            e.path = Some(path.to_path_buf());
+            e
+        })
+    }
+
+    /// Parse and validate manifest text
+    pub fn parse(text: &str) -> Result<Self, ManifestError> {
+        let error_at = |span: Range<usize>, message: String| {
+            let (line, column) = line_col(text, span.start);
+            ManifestError {
+                path: None,
+                line,
+                column,
+                message,
+            }
+        };
+
+        let raw: RawManifest = toml::from_str(text)
+            .map_err(|e| error_at(e.span().unwrap_or(0..0), e.message().trim_end().to_string()))?;
+
+        if let Some(proxy) = &raw.http.proxy {
+            check_proxy(proxy.get_ref()).map_err(|message| error_at(proxy.span(), message))?;
+        }
+        if let Some(user_agent) = &raw.http.user_agent {
+            check_user_agent(user_agent.get_ref())
+                .map_err(|message| error_at(user_agent.span(), message))?;
+        }
+        let seconds = |value: &Option<Spanned<u64>>, key: &str| match value {
+            Some(secs) if *secs.get_ref() == 0 => Err(error_at(
+                secs.span(),
+                format!("`{}` must be at least 1 second", key),
+            )),
+            Some(secs) => Ok(Some(Duration::from_secs(*secs.get_ref()))),
+            None => Ok(None),
+        };
+        let http = ClientConfig {
+            timeout: seconds(&raw.http.timeout, "timeout")?,
+            connect_timeout: seconds(&raw.http.connect_timeout, "connect_timeout")?,
+            proxy: raw.http.proxy.map(Spanned::into_inner),
+            ca_certs: raw.http.ca_certs,
+            user_agent: raw.http.user_agent.map(Spanned::into_inner),
+        };
+
+        if let Some(url) = &raw.modules.raw_base_url {
+            check_url(url.get_ref()).map_err(|e| error_at(url.span(), e))?;
+        }
+        let raw_base_url = raw
+            .modules
+            .raw_base_url
+            .map(|url| url.get_ref().trim_end_matches('/').to_string());
+
+        let mut seen: HashMap<(SourceKind, String), usize> = HashMap::new();
+        let mut sources = Vec::with_capacity(raw.source.len());
+
+        for entry in raw.source {
+            let (line, _) = line_col(text, entry.span().start);
+            let raw = entry.into_inner();
+            let kind = *raw.kind.get_ref();
+            let name = raw.name.get_ref().trim();
+
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return Err(error_at(
+                    raw.name.span(),
+                    format!("invalid source name `{}`", raw.name.get_ref()),
+                ));
+            }
+
+            if let Some(first) = seen.insert((kind, name.to_string()), line) {
+                return Err(error_at(
+                    raw.name.span(),
+                    format!(
+                        "duplicate {} source name `{}` (first defined on line {})",
+                        kind, name, first
+                    ),
+                ));
+            }
+
+            let category = match (&raw.category, kind.categories()) {
+                (None, []) => None,
+                (None, _) => {
+                    return Err(error_at(
+                        raw.kind.span(),
+                        format!("{} source `{}` is missing a category", kind, name),
+                    ));
+                }
+                (Some(category), allowed) => {
+                    let value = category.get_ref();
+                    if !allowed.contains(&value.as_str()) {
+                        let expected = if allowed.is_empty() {
+                            format!("{} sources take no category", kind)
+                        } else {
+                            format!("expected one of: {}", allowed.join(", "))
+                        };
+                        return Err(error_at(
+                            category.span(),
+                            format!("unknown {} category `{}`, {}", kind, value, expected),
+                        ));
+                    }
+                    Some(value.clone())
+                }
+            };
+
+            let inputs = match (&raw.url, &raw.inputs) {
+                (Some(url), None) => {
+                    check_url(url.get_ref()).map_err(|e| error_at(url.span(), e))?;
+                    vec![Input::Remote(url.get_ref().clone())]
+                }
+                (None, Some(inputs)) if kind == SourceKind::Rule => {
+                    if inputs.get_ref().is_empty() {
+                        return Err(error_at(
+                            inputs.span(),
+                            "`inputs` must list at least one URL or file".to_string(),
+                        ));
+                    }
+                    let output = Path::new(category.as_deref().unwrap_or_default())
+                        .join(format!("{}.conf", name));
+                    let mut parsed = Vec::new();
+                    for input in inputs.get_ref() {
+                        let value =
+                            parse_input(input.get_ref()).map_err(|e| error_at(input.span(), e))?;
+                        if parsed.contains(&value) {
+                            return Err(error_at(
+                                input.span(),
+                                format!("duplicate input `{}`", value),
+                            ));
+                        }
+                        if value == Input::Local(output.clone()) {
+                            return Err(error_at(
+                                input.span(),
+                                format!("source `{}` cannot include its own output", name),
+                            ));
+                        }
+                        parsed.push(value);
+                    }
+                    parsed
+                }
+                (None, Some(inputs)) => {
+                    return Err(error_at(
+                        inputs.span(),
+                        format!("{} sources take a single `url`, not `inputs`", kind),
+                    ));
+                }
+                (Some(url), Some(_)) => {
+                    return Err(error_at(
+                        url.span(),
+                        "set either `url` or `inputs`, not both".to_string(),
+                    ));
+                }
+                (None, None) => {
+                    return Err(error_at(
+                        raw.name.span(),
+                        format!("source `{}` has no `url`", name),
+                    ));
+                }
+            };
+
+            if let (Some(_), Some(first)) = (&raw.inputs, raw.mirrors.first()) {
+                return Err(error_at(
+                    first.span(),
+                    "`mirrors` require a single `url`".to_string(),
+                ));
+            }
+            let mut mirrors: Vec<String> = Vec::with_capacity(raw.mirrors.len());
+            for mirror in &raw.mirrors {
+                let value = mirror.get_ref();
+                check_url(value).map_err(|e| error_at(mirror.span(), e))?;
+                if mirrors.contains(value) || raw.url.as_ref().is_some_and(|u| u.get_ref() == value)
+                {
+                    return Err(error_at(
+                        mirror.span(),
+                        format!("duplicate mirror `{}`", value),
+                    ));
+                }
+                mirrors.push(value.clone());
+            }
+
+            let sha256 = match (&raw.sha256, &raw.inputs) {
+                (Some(pin), Some(_)) => {
+                    return Err(error_at(
+                        pin.span(),
+                        "`sha256` requires a single `url`".to_string(),
+                    ));
+                }
+                (Some(pin), None) => {
+                    let value = pin.get_ref().trim();
+                    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(error_at(
+                            pin.span(),
+                            format!("`sha256` must be 64 hex digits, got `{}`", value),
+                        ));
+                    }
+                    Some(value.to_ascii_lowercase())
+                }
+                (None, _) => None,
+            };
+
+            if kind != SourceKind::Rule {
+                if let Some(first) = raw.exclude.first() {
+                    return Err(error_at(
+                        first.span(),
+                        format!("{} sources do not support `exclude`", kind),
+                    ));
+                }
+            }
+            let mut exclude = Vec::with_capacity(raw.exclude.len());
+            for rule in &raw.exclude {
+                let entry = rule
+                    .get_ref()
+                    .parse::<RuleEntry>()
+                    .map_err(|e| error_at(rule.span(), format!("invalid exclude rule: {}", e)))?;
+                exclude.push(entry);
+            }
+
+            for (only, option, span) in raw.options.kind_specific() {
+                if kind != only {
+                    return Err(error_at(
+                        span,
+                        format!("{} sources do not support `{}`", kind, option),
+                    ));
+                }
+            }
+            let options = raw.options.into_options();
+
+            if options.vendor_scripts && raw_base_url.is_none() {
+                return Err(error_at(
+                    raw.name.span(),
+                    format!(
+                        "source `{}` sets `vendor_scripts` but [modules] has no `raw_base_url`",
+                        name
+                    ),
+                ));
+            }
+
+            let patch = match raw.patch {
+                Some(patch) if kind != SourceKind::Module => {
+                    return Err(error_at(
+                        patch.span(),
+                        format!("{} sources do not support `patch`", kind),
+                    ));
+                }
+                Some(patch) => {
+                    let patch = patch.into_inner();
+                    let mut remove = Vec::new();
+                    for (section, patterns) in &patch.remove {
+                        for pattern in patterns {
+                            let regex = Regex::new(pattern.get_ref()).map_err(|e| {
+                                error_at(pattern.span(), format!("invalid patch pattern: {}", e))
+                            })?;
+                            remove.push((SectionKind::from_name(section), regex));
+                        }
+                    }
+                    let mut append = Vec::new();
+                    for (section, lines) in &patch.append {
+                        for line in lines {
+                            if line.get_ref().contains('\n')
+                                || line.get_ref().trim().starts_with('[')
+                            {
+                                return Err(error_at(
+                                    line.span(),
+                                    "appended lines must be single module lines, not sections"
+                                        .to_string(),
+                                ));
+                            }
+                        }
+                        append.push((
+                            SectionKind::from_name(section),
+                            lines.iter().map(|line| line.get_ref().clone()).collect(),
+                        ));
+                    }
+                    Some(Patch {
+                        name: patch.name,
+                        desc: patch.desc,
+                        remove,
+                        append,
+                    })
+                }
+                None => None,
+            };
+
+            sources.push(Source {
+                name: name.to_string(),
+                kind,
+                category,
+                inputs,
+                mirrors,
+                sha256,
+                exclude,
+                patch,
+                options,
+                line,
+            });
+        }
+
+        Ok(Self {
+            sources,
+            http,
+            raw_base_url,
+        })
+    }
+
+    /// Enabled sources of the given kind, in manifest order
+    pub fn sources_of(&self, kind: SourceKind) -> impl Iterator<Item = &Source> {
+        self.sources
+            .iter()
+            .filter(move |source| source.kind == kind && source.options.enabled)
+    }
+}
+
+/// True if `line` assigns the TOML key `key`
+fn assigns(line: &str, key: &str) -> bool {
+    line.trim_start()
+        .strip_prefix(key)
+        .is_some_and(|rest| rest.trim_start().starts_with('='))
+}
+
+/// Rewrite manifest text so each source in `pins`, keyed by the line of its
+/// `[[source]]` header, carries the given `sha256`. An existing pin is
+/// replaced in place; a new one goes right after the source's `url`. All
+/// other text, comments included, is left untouched.
+pub fn set_pins(text: &str, pins: &BTreeMap<usize, String>) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    // Work bottom-up so insertions do not shift the lines still to visit
+    for (&line, hash) in pins.iter().rev() {
+        let start = line.saturating_sub(1);
+        if start >= lines.len() {
+            continue;
+        }
+        let end = lines[start + 1..]
+            .iter()
+            .position(|l| l.trim_start().starts_with('['))
+            .map_or(lines.len(), |offset| start + 1 + offset);
+        let block = start + 1..end;
+        let pin = format!("sha256 = \"{}\"", hash);
+
+        if let Some(i) = block.clone().find(|&i| assigns(&lines[i], "sha256")) {
+            let indent: String = lines[i].chars().take_while(|c| c.is_whitespace()).collect();
+            lines[i] = format!("{}{}", indent, pin);
+        } else {
+            let at = block
+                .clone()
+                .find(|&i| assigns(&lines[i], "url"))
+                .unwrap_or(start);
+            lines.insert(at + 1, pin);
+        }
+    }
+
+    let mut result = lines.join("\n");
+    if text.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid_manifest() {
+        let text = r#"
+[[source]]
+name = "ai"
+kind = "rule"
+category = "ai"
+url = "https://ruleset.skk.moe/List/non_ip/ai.conf"
+options = { on_invalid = "fail", exports = ["clash"], format = "abp" }
+
+[[source]]
+name = "Country"
+kind = "geoip"
+url = "https://example.com/Country.mmdb"
+options = { enabled = false }
+"#;
+        let manifest = Manifest::parse(text).unwrap();
+        assert_eq!(manifest.sources.len(), 2);
+        assert_eq!(manifest.sources[0].line, 2);
+        assert_eq!(manifest.sources[0].category.as_deref(), Some("ai"));
+        assert_eq!(
+            manifest.sources[0].options.on_invalid,
+            InvalidLinePolicy::Fail
+        );
+        assert_eq!(
+            manifest.sources[0].options.exports,
+            vec![ExportTarget::Clash]
+        );
+        assert_eq!(manifest.sources[0].options.format, InputFormat::Adguard);
+        assert_eq!(manifest.sources_of(SourceKind::Rule).count(), 1);
+        assert_eq!(manifest.sources_of(SourceKind::Geoip).count(), 0);
+    }
+
+    #[test]
+    fn test_module_arguments() {
+        let text = r#"[[source]]
+name = "bilibili"
+kind = "module"
+category = "enhance"
+url = "https://e.com/bilibili.sgmodule"
+options = { arguments = { "空降助手策略" = "Proxy", "日志等级" = "2" }, arguments_mode = "render" }
+"#;
+        let options = &Manifest::parse(text).unwrap().sources[0].options;
+        assert_eq!(options.arguments["空降助手策略"], "Proxy");
+        assert_eq!(options.arguments.len(), 2);
+        assert_eq!(options.arguments_mode, ArgumentMode::Render);
+
+        let text = text.replace("\"render\"", "\"inline\"");
+        assert_eq!(Manifest::parse(&text).unwrap_err().line, 6);
+    }
+
+    #[test]
+    fn test_vendor_scripts_need_raw_base_url() {
+        let source = "[[source]]\nname = \"a\"\nkind = \"module\"\ncategory = \"utility\"\nurl = \"https://e.com/a.sgmodule\"\noptions = { vendor_scripts = true }\n";
+        let err = Manifest::parse(source).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("raw_base_url"), "{}", err.message);
+
+        let text = format!(
+            "[modules]\nraw_base_url = \"https://raw.githubusercontent.com/u/r/main/\"\n\n{}",
+            source
+        );
+        assert_eq!(
+            Manifest::parse(&text).unwrap().raw_base_url.as_deref(),
+            Some("https://raw.githubusercontent.com/u/r/main")
+        );
+
+        let err = Manifest::parse("[modules]\nraw_base_url = \"ftp://e.com\"\n").unwrap_err();
+        assert!(
+            err.message.contains("unsupported URL scheme"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn test_module_patch() {
+        let text = r#"[[source]]
+name = "spotify"
+kind = "module"
+category = "utility"
+url = "https://e.com/spotify.sgmodule"
+
+[source.patch]
+name = "Spotify (no lyrics)"
+remove = { Script = ["^spotify-lyric "], MITM = ["^api\\.x\\.com$"] }
+append = { Rule = ["DOMAIN,ads.x.com,REJECT"] }
+"#;
+        let patch = Manifest::parse(text).unwrap().sources[0]
+            .patch
+            .clone()
+            .unwrap();
+        assert_eq!(patch.name.as_deref(), Some("Spotify (no lyrics)"));
+        let removed: Vec<(&str, &str)> = patch
+            .remove
+            .iter()
+            .map(|(kind, regex)| (kind.name(), regex.as_str()))
+            .collect();
+        assert_eq!(
+            removed,
+            [("MITM", r"^api\.x\.com$"), ("Script", "^spotify-lyric ")]
+        );
+        assert_eq!(patch.append[0].0, SectionKind::Rule);
+
+        let err = Manifest::parse(&text.replace("^spotify-lyric ", "(")).unwrap_err();
+        assert_eq!(err.line, 9);
+        assert!(
+            err.message.contains("invalid patch pattern"),
+            "{}",
+            err.message
+        );
+
+        let err = Manifest::parse(&text.replace("DOMAIN,ads", "[Rule]")).unwrap_err();
+        assert_eq!(err.line, 10);
+
+        let rule = text.replace(
+            "kind = \"module\"\ncategory = \"utility\"",
+            "kind = \"rule\"\ncategory = \"ai\"",
+        );
+        let err = Manifest::parse(&rule).unwrap_err();
+        assert!(
+            err.message.contains("do not support `patch`"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn test_duplicate_name_reports_line() {
+        let text = r#"[[source]]
+name = "ai"
+kind = "rule"
+category = "ai"
+url = "https://example.com/a.conf"
+
+[[source]]
+name = "ai"
+kind = "rule"
+category = "ai"
+url = "https://example.com/b.conf"
+"#;
+        let err = Manifest::parse(text).unwrap_err();
+        assert_eq!(err.line, 8);
+        assert!(err.message.contains("first defined on line 1"));
+    }
+
+    #[test]
+    fn test_options_checked_against_kind() {
+        let source = |kind: &str, category: &str, options: &str| {
+            format!(
+                "[[source]]\nname = \"x\"\nkind = \"{}\"\noptions = {{ retries = 1, {} }}\ncategory = \"{}\"\nurl = \"https://e.com/x\"\n",
+                kind, options, category
+            )
+        };
+        let cases = [
+            (
+                "rule",
+                "ai",
+                "vendor_scripts = false",
+                "rule sources do not support `vendor_scripts`",
+            ),
+            (
+                "module",
+                "utility",
+                "exports = [\"clash\"]",
+                "module sources do not support `exports`",
+            ),
+            (
+                "icon",
+                "apps",
+                "exports = [\"clash\"]",
+                "icon sources do not support `exports`",
+            ),
+            (
+                "geoip",
+                "",
+                "min_entries = 0",
+                "geoip sources do not support `min_entries`",
+            ),
+        ];
+        for (kind, category, options, message) in cases {
+            let text = source(kind, category, options).replace("category = \"\"\n", "");
+            let err = Manifest::parse(&text).unwrap_err();
+            assert_eq!(err.line, 4, "{}", kind);
+            assert_eq!(err.message, message);
+        }
+
+        // Options every kind shares are accepted anywhere
+        let text = source("icon", "apps", "cdn_mirrors = true");
+        assert!(
+            Manifest::parse(&text).unwrap().sources[0]
+                .options
+                .cdn_mirrors
+        );
+    }
+
+    #[test]
+    fn test_same_name_allowed_across_kinds() {
+        let text = r#"[[source]]
+name = "spotify"
+kind = "rule"
+category = "media"
+url = "https://example.com/a.conf"
+
+[[source]]
+name = "spotify"
+kind = "icon"
+category = "apps"
+url = "https://example.com/a.png"
+"#;
+        assert!(Manifest::parse(text).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_category_and_bad_url() {
+        let text = "[[source]]\nname = \"x\"\nkind = \"module\"\ncategory = \"ai\"\nurl = \"https://e.com\"\n";
+        let err = Manifest::parse(text).unwrap_err();
+        assert_eq!((err.line, err.column), (4, 12));
+
+        let text = "[[source]]\nname = \"x\"\nkind = \"icon\"\ncategory = \"apps\"\nurl = \"ftp://e.com/x.png\"\n";
+        assert_eq!(Manifest::parse(text).unwrap_err().line, 5);
+
+        let text =
+            "[[source]]\nname = \"x\"\nkind = \"icon\"\ncategory = \"apps\"\nurl = \"not a url\"\n";
+        assert_eq!(Manifest::parse(text).unwrap_err().line, 5);
+    }
+
+    #[test]
+    fn test_singbox_version_is_checked() {
+        let text = "[[source]]\nname = \"x\"\nkind = \"rule\"\ncategory = \"ai\"\nurl = \"https://e.com\"\noptions = { exports = [\"singbox\"], singbox_version = 3 }\n";
+        let err = Manifest::parse(text).unwrap_err();
+        assert_eq!(err.line, 6);
+        assert!(err.message.contains("expected 1 or 2"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_source_mirrors() {
+        let text = "[[source]]\nname = \"x\"\nkind = \"rule\"\ncategory = \"ai\"\nurl = \"https://raw.githubusercontent.com/u/r/main/x.conf\"\nmirrors = [\"https://m.com/x.conf\"]\noptions = { cdn_mirrors = true }\n";
+        let manifest = Manifest::parse(text).unwrap();
+        let source = &manifest.sources[0];
+        assert_eq!(
+            source.mirrors_for(source.url()),
+            vec![
+                "https://m.com/x.conf",
+                "https://cdn.jsdelivr.net/gh/u/r@main/x.conf",
+                "https://fastly.jsdelivr.net/gh/u/r@main/x.conf"
+            ]
+        );
+
+        let text = "[[source]]\nname = \"x\"\nkind = \"rule\"\ncategory = \"ai\"\nurl = \"https://e.com/x.conf\"\nmirrors = [\"https://e.com/x.conf\"]\n";
+        let err = Manifest::parse(text).unwrap_err();
+        assert_eq!(err.line, 6);
+        assert!(err.message.contains("duplicate mirror"), "{}", err.message);
+
+        let text = "[[source]]\nname = \"x\"\nkind = \"rule\"\ncategory = \"ai\"\ninputs = [\"https://e.com/a.conf\", \"https://e.com/b.conf\"]\nmirrors = [\"https://m.com/a.conf\"]\n";
+        let err = Manifest::parse(text).unwrap_err();
+        assert!(
+            err.message.contains("require a single `url`"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn test_http_settings() {
+        let text = "[http]\nproxy = \"socks5h://127.0.0.1:1080\"\nca_certs = [\"certs/corp.pem\"]\ntimeout = 60\n";
+        let manifest = Manifest::parse(text).unwrap();
+        assert_eq!(
+            manifest.http.proxy.as_deref(),
+            Some("socks5h://127.0.0.1:1080")
+        );
+        assert_eq!(
+            manifest.http.ca_certs,
+            vec![PathBuf::from("certs/corp.pem")]
+        );
+        assert_eq!(manifest.http.timeout, Some(Duration::from_secs(60)));
+        assert_eq!(manifest.http.connect_timeout, None);
+
+        let err = Manifest::parse("[http]\nproxy = \"ftp://p:21\"\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(
+            err.message.contains("unsupported proxy scheme"),
+            "{}",
+            err.message
+        );
+
+        let err = Manifest::parse("[http]\nconnect_timeout = 0\n").unwrap_err();
+        assert!(err.message.contains("at least 1 second"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_sha256_pins() {
+        let pin = "ab".repeat(32);
+        let text = format!("# sources\n\n[[source]]\nname = \"a\"\nkind = \"module\"\ncategory = \"utility\"\nurl = \"https://e.com/a.sgmodule\"\nsha256 = \"{}\"\n\n[[source]]\nname = \"b\"\nkind = \"module\"\ncategory = \"utility\"\nurl = \"https://e.com/b.sgmodule\"\noptions = {{ retries = 1 }}\n", pin.to_uppercase());
+        let manifest = Manifest::parse(&text).unwrap();
+        assert_eq!(manifest.sources[0].sha256.as_deref(), Some(pin.as_str()));
+        assert_eq!(manifest.sources[1].sha256, None);
+
+        let new_pin = "cd".repeat(32);
+        let pins: BTreeMap<usize, String> = manifest
+            .sources
+            .iter()
+            .map(|source| (source.line, new_pin.clone()))
+            .collect();
+        let pinned = set_pins(&text, &pins);
+        assert!(pinned.starts_with("# sources\n"));
+        let manifest = Manifest::parse(&pinned).unwrap();
+        assert!(manifest
+            .sources
+            .iter()
+            .all(|source| source.sha256.as_deref() == Some(new_pin.as_str())));
+        assert_eq!(pinned.matches("sha256").count(), 2);
+        assert!(pinned.contains("b.sgmodule\"\nsha256 = "));
+
+        let err = Manifest::parse("[[source]]\nname = \"a\"\nkind = \"icon\"\ncategory = \"apps\"\nurl = \"https://e.com/a.json\"\nsha256 = \"abc\"\n").unwrap_err();
+        assert_eq!(err.line, 6);
+        assert!(err.message.contains("64 hex digits"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_unknown_kind_reports_line() {
+        let text = "[[source]]\nname = \"x\"\nkind = \"ruleset\"\nurl = \"https://e.com\"\n";
+        let err = Manifest::parse(text).unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_repo_manifest_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(MANIFEST_FILE);
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(manifest.sources_of(SourceKind::Geoip).count(), 1);
+    }
+
+    #[test]
+    fn test_composite_rule_source() {
+        let text = r#"[[source]]
+name = "ai"
+kind = "rule"
+category = "ai"
+inputs = ["https://ruleset.skk.moe/List/non_ip/ai.conf", "private/private-ai.conf"]
+exclude = ["DOMAIN-SUFFIX,x.ai"]
+"#;
+        let manifest = Manifest::parse(text).unwrap();
+        let source = &manifest.sources[0];
+        assert!(source.is_composite());
+        assert_eq!(source.url(), "https://ruleset.skk.moe/List/non_ip/ai.conf");
+        assert_eq!(
+            source.inputs[1],
+            Input::Local(PathBuf::from("private/private-ai.conf"))
+        );
+        assert_eq!(source.exclude.len(), 1);
+    }
+
+    #[test]
+    fn test_composite_rule_source_errors() {
+        let base = "[[source]]\nname = \"ai\"\nkind = \"rule\"\ncategory = \"ai\"\n";
+        for (extra, line) in [
+            ("inputs = [\"../secret.conf\"]\n", 5),
+            ("inputs = [\"ai/ai.conf\"]\n", 5),
+            ("inputs = []\n", 5),
+            ("inputs = [\"a.conf\",\n  \"a.conf\"]\n", 6),
+            ("url = \"https://e.com/a\"\ninputs = [\"a.conf\"]\n", 5),
+            ("inputs = [\"a.conf\"]\nexclude = [\"GEOSITE,x\"]\n", 6),
+        ] {
+            let err = Manifest::parse(&format!("{}{}", base, extra)).unwrap_err();
+            assert_eq!(err.line, line, "{}", extra);
+        }
+
+        let text = "[[source]]\nname = \"x\"\nkind = \"icon\"\ncategory = \"apps\"\ninputs = [\"https://e.com/x.png\"]\n";
+        assert_eq!(Manifest::parse(text).unwrap_err().line, 5);
+    }
+}
@@ -0,0 +1,1035 @@
+//! Surge module parser
+//!
+//! Splits a `.sgmodule` file into its `#!` metadata and its sections, and
+//! parses the entries of the sections Surge documents into typed [`Entry`]s.
+//! Every line keeps the text it was read from, so [`Module::to_text`]
+//! reproduces the input exactly; only entries that are replaced are written
+//! in canonical form.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::rule::{is_comment_or_blank, RuleEntry};
+
+/// The section a line belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    General,
+    Rule,
+    Script,
+    UrlRewrite,
+    HeaderRewrite,
+    MapLocal,
+    BodyRewrite,
+    Mitm,
+    /// A section this parser does not model, e.g. `[Host]`
+    Other(String),
+}
+
+impl SectionKind {
+    /// Kind for a section header name, e.g. `URL Rewrite`
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "General" => SectionKind::General,
+            "Rule" => SectionKind::Rule,
+            "Script" => SectionKind::Script,
+            "URL Rewrite" => SectionKind::UrlRewrite,
+            "Header Rewrite" => SectionKind::HeaderRewrite,
+            "Map Local" => SectionKind::MapLocal,
+            "Body Rewrite" => SectionKind::BodyRewrite,
+            "MITM" => SectionKind::Mitm,
+            other => SectionKind::Other(other.to_string()),
+        }
+    }
+
+    /// Section header name as Surge writes it
+    pub fn name(&self) -> &str {
+        match self {
+            SectionKind::General => "General",
+            SectionKind::Rule => "Rule",
+            SectionKind::Script => "Script",
+            SectionKind::UrlRewrite => "URL Rewrite",
+            SectionKind::HeaderRewrite => "Header Rewrite",
+            SectionKind::MapLocal => "Map Local",
+            SectionKind::BodyRewrite => "Body Rewrite",
+            SectionKind::Mitm => "MITM",
+            SectionKind::Other(name) => name,
+        }
+    }
+}
+
+/// A `key=value` parameter of a script or map-local entry. The value keeps
+/// its quotes as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub key: String,
+    pub value: String,
+}
+
+impl Param {
+    /// The value without surrounding double quotes
+    pub fn unquoted(&self) -> &str {
+        self.value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(&self.value)
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// True if `text` starts with a parameter key followed by `=`
+fn starts_with_key(text: &str) -> bool {
+    let key_len = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(text.len());
+    key_len > 0 && text[key_len..].starts_with('=')
+}
+
+/// Split `key=value` parameters separated by `sep`.
+///
+/// Values may contain the separator: a value only ends where the separator
+/// is followed by another `key=`, and a quoted value additionally only ends
+/// after its closing quote. This keeps regex patterns and JSON arguments in
+/// one piece. Returns `None` if a parameter has no `=`.
+fn split_params(text: &str, sep: char) -> Option<Vec<Param>> {
+    let mut params = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (key, value_and_rest) = rest.split_once('=')?;
+        if !starts_with_key(rest) {
+            return None;
+        }
+        let quoted = value_and_rest.starts_with('"');
+        let end = value_and_rest
+            .match_indices(sep)
+            .map(|(i, _)| i)
+            .find(|&i| {
+                (!quoted || (i > 0 && value_and_rest[..i].ends_with('"')))
+                    && starts_with_key(value_and_rest[i + 1..].trim_start())
+            })
+            .unwrap_or(value_and_rest.len());
+        params.push(Param {
+            key: key.trim().to_string(),
+            value: value_and_rest[..end].trim().to_string(),
+        });
+        rest = value_and_rest[end..]
+            .strip_prefix(sep)
+            .unwrap_or_default()
+            .trim_start();
+    }
+    Some(params)
+}
+
+fn join_params(params: &[Param], sep: &str) -> String {
+    params
+        .iter()
+        .map(Param::to_string)
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+/// A `[Script]` entry: `name = type=http-response,pattern=...,script-path=...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub name: String,
+    pub params: Vec<Param>,
+}
+
+impl Script {
+    /// Unquoted value of a parameter
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|param| param.key == key)
+            .map(Param::unquoted)
+    }
+
+    /// Replace a parameter's value, or add the parameter at the end
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.params.iter_mut().find(|param| param.key == key) {
+            Some(param) => param.value = value.to_string(),
+            None => self.params.push(Param {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    pub fn script_path(&self) -> Option<&str> {
+        self.get("script-path")
+    }
+
+    pub fn requires_body(&self) -> bool {
+        matches!(self.get("requires-body"), Some("1" | "true"))
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.name, join_params(&self.params, ","))
+    }
+}
+
+/// A `key = value` line in `[General]` or `[MITM]`, with list values split
+/// on commas and an optional `%APPEND%`-style modifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub key: String,
+    /// `%APPEND%`, `%INSERT%` or `%REPLACE%`
+    pub modifier: Option<String>,
+    pub values: Vec<String>,
+}
+
+impl Setting {
+    fn parse(text: &str) -> Option<Self> {
+        let (key, value) = text.split_once('=')?;
+        let key = key.trim();
+        if key.is_empty() {
+            return None;
+        }
+        let mut value = value.trim();
+        let mut modifier = None;
+        if value.starts_with('%') {
+            if let Some(end) = value[1..].find('%') {
+                modifier = Some(value[..end + 2].to_string());
+                value = value[end + 2..].trim_start();
+            }
+        }
+        let values = value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect();
+        Some(Setting {
+            key: key.to_string(),
+            modifier,
+            values,
+        })
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} =", self.key)?;
+        if let Some(modifier) = &self.modifier {
+            write!(f, " {}", modifier)?;
+        }
+        if !self.values.is_empty() {
+            write!(f, " {}", self.values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A parsed module line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// `#!key=value` metadata before the first section
+    Meta {
+        key: String,
+        value: String,
+    },
+    General(Setting),
+    Rule(RuleEntry),
+    Script(Script),
+    /// `pattern replacement mode`, e.g. `^http://a.com https://b.com 302`
+    UrlRewrite {
+        pattern: String,
+        replacement: String,
+        mode: String,
+    },
+    /// `[http-request|http-response] pattern action...`
+    HeaderRewrite {
+        phase: Option<String>,
+        pattern: String,
+        action: String,
+    },
+    /// `pattern data-type=... data="..."`
+    MapLocal {
+        pattern: String,
+        params: Vec<Param>,
+    },
+    /// `http-response[-jq] pattern arguments...`
+    BodyRewrite {
+        phase: String,
+        pattern: String,
+        action: String,
+    },
+    Mitm(Setting),
+    /// A line of an unmodeled section, or one that could not be parsed
+    Other(String),
+}
+
+/// Split off the first whitespace-separated token
+fn first_token(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim();
+    let end = text.find(char::is_whitespace)?;
+    Some((&text[..end], text[end..].trim_start()))
+}
+
+impl Entry {
+    /// Parse the trimmed text of a non-comment line in `kind`, or in the head
+    /// before any section if `kind` is `None`
+    fn parse(kind: Option<&SectionKind>, text: &str) -> Entry {
+        let parsed = match kind {
+            None => text.strip_prefix("#!").and_then(|meta| {
+                let (key, value) = meta.split_once('=')?;
+                Some(Entry::Meta {
+                    key: key.trim().to_string(),
+                    value: value.trim().to_string(),
+                })
+            }),
+            Some(SectionKind::General) => Setting::parse(text).map(Entry::General),
+            Some(SectionKind::Mitm) => Setting::parse(text).map(Entry::Mitm),
+            Some(SectionKind::Rule) => text.parse().ok().map(Entry::Rule),
+            Some(SectionKind::Script) => text.split_once('=').and_then(|(name, params)| {
+                Some(Entry::Script(Script {
+                    name: name.trim().to_string(),
+                    params: split_params(params, ',')?,
+                }))
+            }),
+            Some(SectionKind::UrlRewrite) => {
+                let parts: Vec<&str> = text.split_whitespace().collect();
+                match parts[..] {
+                    [pattern, replacement, mode] => Some(Entry::UrlRewrite {
+                        pattern: pattern.to_string(),
+                        replacement: replacement.to_string(),
+                        mode: mode.to_string(),
+                    }),
+                    _ => None,
+                }
+            }
+            Some(SectionKind::HeaderRewrite) => first_token(text).and_then(|(first, rest)| {
+                let (phase, pattern, action) = if first.starts_with("http-") {
+                    let (pattern, action) = first_token(rest)?;
+                    (Some(first.to_string()), pattern, action)
+                } else {
+                    (None, first, rest)
+                };
+                Some(Entry::HeaderRewrite {
+                    phase,
+                    pattern: pattern.to_string(),
+                    action: action.to_string(),
+                })
+            }),
+            Some(SectionKind::MapLocal) => first_token(text).and_then(|(pattern, params)| {
+                Some(Entry::MapLocal {
+                    pattern: pattern.to_string(),
+                    params: split_params(params, ' ')?,
+                })
+            }),
+            Some(SectionKind::BodyRewrite) => first_token(text).and_then(|(phase, rest)| {
+                let (pattern, action) = first_token(rest)?;
+                Some(Entry::BodyRewrite {
+                    phase: phase.to_string(),
+                    pattern: pattern.to_string(),
+                    action: action.to_string(),
+                })
+            }),
+            Some(SectionKind::Other(_)) => None,
+        };
+        parsed.unwrap_or_else(|| Entry::Other(text.to_string()))
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Meta { key, value } => write!(f, "#!{}={}", key, value),
+            Entry::General(setting) | Entry::Mitm(setting) => setting.fmt(f),
+            Entry::Rule(rule) => rule.fmt(f),
+            Entry::Script(script) => script.fmt(f),
+            Entry::UrlRewrite {
+                pattern,
+                replacement,
+                mode,
+            } => write!(f, "{} {} {}", pattern, replacement, mode),
+            Entry::HeaderRewrite {
+                phase,
+                pattern,
+                action,
+            } => {
+                if let Some(phase) = phase {
+                    write!(f, "{} ", phase)?;
+                }
+                write!(f, "{} {}", pattern, action)
+            }
+            Entry::MapLocal { pattern, params } => {
+                write!(f, "{} {}", pattern, join_params(params, " "))
+            }
+            Entry::BodyRewrite {
+                phase,
+                pattern,
+                action,
+            } => write!(f, "{} {} {}", phase, pattern, action),
+            Entry::Other(text) => f.write_str(text),
+        }
+    }
+}
+
+/// One line of a module: the text as written and, unless it is blank or a
+/// comment, its parsed entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    text: String,
+    entry: Option<Entry>,
+}
+
+impl Line {
+    /// A line holding `entry` in canonical form
+    pub fn new(entry: Entry) -> Self {
+        Self {
+            text: entry.to_string(),
+            entry: Some(entry),
+        }
+    }
+
+    fn parse(kind: Option<&SectionKind>, text: &str) -> Self {
+        let is_meta = kind.is_none() && text.trim_start().starts_with("#!");
+        let entry =
+            (is_meta || !is_comment_or_blank(text)).then(|| Entry::parse(kind, text.trim()));
+        Self {
+            text: text.to_string(),
+            entry,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn entry(&self) -> Option<&Entry> {
+        self.entry.as_ref()
+    }
+}
+
+/// A `[Name]` section and its lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The header line as written
+    pub header: String,
+    pub kind: SectionKind,
+    pub lines: Vec<Line>,
+}
+
+impl Section {
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(Line::entry)
+    }
+}
+
+/// A module argument declared in `#!arguments`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argument {
+    pub name: String,
+    pub default: String,
+}
+
+/// Why argument overrides could not be applied to a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgumentError {
+    /// The module declares no argument of this name
+    Unknown { name: String, declared: Vec<String> },
+    /// The value cannot be written into the module
+    InvalidValue { name: String, reason: &'static str },
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgumentError::Unknown { name, declared } if declared.is_empty() => write!(
+                f,
+                "unknown module argument `{}`: the module declares no #!arguments",
+                name
+            ),
+            ArgumentError::Unknown { name, declared } => write!(
+                f,
+                "unknown module argument `{}`, expected one of: {}",
+                name,
+                declared.join(", ")
+            ),
+            ArgumentError::InvalidValue { name, reason } => {
+                write!(f, "value for module argument `{}` {}", name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+/// Declarative edits to a downloaded module
+#[derive(Debug, Clone, Default)]
+pub struct Patch {
+    /// Replacement `#!name`
+    pub name: Option<String>,
+    /// Replacement `#!desc`
+    pub desc: Option<String>,
+    /// Entries to drop from each section: lines whose text matches, or for
+    /// `[MITM]` and `[General]` settings, the list values that match
+    pub remove: Vec<(SectionKind, Regex)>,
+    /// Lines to add at the end of each section, which is created if missing
+    pub append: Vec<(SectionKind, Vec<String>)>,
+}
+
+/// A parsed Surge module
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Module {
+    /// Lines before the first section: metadata, comments and blank lines
+    pub head: Vec<Line>,
+    pub sections: Vec<Section>,
+    trailing_newline: bool,
+}
+
+impl Module {
+    /// Parse a module. Lines that do not fit their section are kept as
+    /// [`Entry::Other`] rather than rejected.
+    pub fn parse(text: &str) -> Self {
+        let mut module = Module {
+            trailing_newline: text.ends_with('\n'),
+            ..Module::default()
+        };
+        if text.is_empty() {
+            return module;
+        }
+        for line in text.strip_suffix('\n').unwrap_or(text).split('\n') {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                module.sections.push(Section {
+                    header: line.to_string(),
+                    kind: SectionKind::from_name(&trimmed[1..trimmed.len() - 1]),
+                    lines: Vec::new(),
+                });
+                continue;
+            }
+            match module.sections.last_mut() {
+                Some(section) => section.lines.push(Line::parse(Some(&section.kind), line)),
+                None => module.head.push(Line::parse(None, line)),
+            }
+        }
+        module
+    }
+
+    /// The module text, identical to the parsed input for unchanged lines
+    pub fn to_text(&self) -> String {
+        let lines = self
+            .head
+            .iter()
+            .map(Line::text)
+            .chain(self.sections.iter().flat_map(|section| {
+                std::iter::once(section.header.as_str()).chain(section.lines.iter().map(Line::text))
+            }));
+        let mut out = lines.collect::<Vec<_>>().join("\n");
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Value of a `#!key=value` metadata line
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.head.iter().find_map(|line| match line.entry() {
+            Some(Entry::Meta { key: k, value }) if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Replace a metadata value, or add it after the existing metadata
+    pub fn set_meta(&mut self, key: &str, value: &str) {
+        let line = Line::new(Entry::Meta {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        let is_meta = |l: &Line| matches!(l.entry(), Some(Entry::Meta { .. }));
+        if let Some(existing) = self
+            .head
+            .iter_mut()
+            .find(|l| matches!(l.entry(), Some(Entry::Meta { key: k, .. }) if k == key))
+        {
+            *existing = line;
+        } else {
+            let at = self.head.iter().rposition(is_meta).map_or(0, |i| i + 1);
+            self.head.insert(at, line);
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.meta("name")
+    }
+
+    pub fn desc(&self) -> Option<&str> {
+        self.meta("desc")
+    }
+
+    pub fn category(&self) -> Option<&str> {
+        self.meta("category")
+    }
+
+    /// Arguments declared as `#!arguments=name:default,name:default`
+    pub fn arguments(&self) -> Vec<Argument> {
+        self.meta("arguments")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| {
+                let (name, default) = item.split_once(':').unwrap_or((item, ""));
+                Argument {
+                    name: name.trim().to_string(),
+                    default: default.trim().to_string(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn arguments_desc(&self) -> Option<&str> {
+        self.meta("arguments-desc")
+    }
+
+    /// Declared arguments with `overrides` applied, or an error naming the
+    /// first override the module does not declare
+    fn resolve_arguments(
+        &self,
+        overrides: &BTreeMap<String, String>,
+    ) -> Result<Vec<Argument>, ArgumentError> {
+        let mut arguments = self.arguments();
+        for (name, value) in overrides {
+            if value.contains(['\n', '\r']) {
+                return Err(ArgumentError::InvalidValue {
+                    name: name.clone(),
+                    reason: "cannot span several lines",
+                });
+            }
+            match arguments.iter_mut().find(|argument| argument.name == *name) {
+                Some(argument) => argument.default = value.clone(),
+                None => {
+                    return Err(ArgumentError::Unknown {
+                        name: name.clone(),
+                        declared: arguments.into_iter().map(|a| a.name).collect(),
+                    })
+                }
+            }
+        }
+        Ok(arguments)
+    }
+
+    /// Rewrite the `#!arguments` defaults with `overrides`
+    pub fn set_argument_defaults(
+        &mut self,
+        overrides: &BTreeMap<String, String>,
+    ) -> Result<(), ArgumentError> {
+        if let Some((name, _)) = overrides.iter().find(|(_, value)| value.contains(',')) {
+            return Err(ArgumentError::InvalidValue {
+                name: name.clone(),
+                reason: "cannot contain commas in #!arguments",
+            });
+        }
+        let arguments = self.resolve_arguments(overrides)?;
+        if arguments.is_empty() {
+            return Ok(());
+        }
+        let value = arguments
+            .iter()
+            .map(|argument| format!("{}:{}", argument.name, argument.default))
+            .collect::<Vec<_>>()
+            .join(",");
+        self.set_meta("arguments", &value);
+        Ok(())
+    }
+
+    /// A copy of the module with every `{{{name}}}` placeholder of a declared
+    /// argument replaced by its override or default, and without the
+    /// `#!arguments` metadata it no longer takes
+    pub fn render_arguments(
+        &self,
+        overrides: &BTreeMap<String, String>,
+    ) -> Result<Module, ArgumentError> {
+        let arguments = self.resolve_arguments(overrides)?;
+        let mut module = self.clone();
+        module.head.retain(|line| {
+            !matches!(line.entry(), Some(Entry::Meta { key, .. })
+                if key == "arguments" || key == "arguments-desc")
+        });
+        let mut text = module.to_text();
+        for argument in &arguments {
+            text = text.replace(&format!("{{{{{{{}}}}}}}", argument.name), &argument.default);
+        }
+        Ok(Module::parse(&text))
+    }
+
+    /// Every entry in sections of `kind`
+    pub fn entries<'a>(&'a self, kind: &'a SectionKind) -> impl Iterator<Item = &'a Entry> {
+        self.sections
+            .iter()
+            .filter(move |section| &section.kind == kind)
+            .flat_map(Section::entries)
+    }
+
+    pub fn scripts(&self) -> impl Iterator<Item = &Script> {
+        self.entries(&SectionKind::Script)
+            .filter_map(|entry| match entry {
+                Entry::Script(script) => Some(script),
+                _ => None,
+            })
+    }
+
+    /// Drop the entries of `kind` sections matching `pattern`. Returns how
+    /// many lines or setting values were removed.
+    pub fn remove_matching(&mut self, kind: &SectionKind, pattern: &Regex) -> usize {
+        let mut removed = 0;
+        for section in self.sections.iter_mut().filter(|s| &s.kind == kind) {
+            let mut kept = Vec::with_capacity(section.lines.len());
+            for line in section.lines.drain(..) {
+                match line.entry() {
+                    Some(Entry::Mitm(setting)) | Some(Entry::General(setting)) => {
+                        let mut setting = setting.clone();
+                        let before = setting.values.len();
+                        setting.values.retain(|value| !pattern.is_match(value));
+                        removed += before - setting.values.len();
+                        if setting.values.len() == before {
+                            kept.push(line);
+                        } else if !setting.values.is_empty() {
+                            let entry = match line.entry() {
+                                Some(Entry::Mitm(_)) => Entry::Mitm(setting),
+                                _ => Entry::General(setting),
+                            };
+                            kept.push(Line::new(entry));
+                        }
+                    }
+                    Some(_) if pattern.is_match(line.text().trim()) => removed += 1,
+                    _ => kept.push(line),
+                }
+            }
+            section.lines = kept;
+        }
+        removed
+    }
+
+    /// Add lines after the last entry of the first `kind` section, creating
+    /// the section at the end of the module if there is none
+    pub fn append_lines(&mut self, kind: &SectionKind, lines: &[String]) {
+        let index = match self.sections.iter().position(|s| &s.kind == kind) {
+            Some(index) => index,
+            None => {
+                // Keep a blank line between the previous content and the new section
+                let last = match self.sections.last_mut() {
+                    Some(section) => &mut section.lines,
+                    None => &mut self.head,
+                };
+                if last
+                    .last()
+                    .is_some_and(|line| !line.text().trim().is_empty())
+                {
+                    last.push(Line::parse(None, ""));
+                }
+                self.sections.push(Section {
+                    header: format!("[{}]", kind.name()),
+                    kind: kind.clone(),
+                    lines: Vec::new(),
+                });
+                self.sections.len() - 1
+            }
+        };
+        let section = &mut self.sections[index];
+        let at = section
+            .lines
+            .iter()
+            .rposition(|line| !line.text().trim().is_empty())
+            .map_or(0, |i| i + 1);
+        let new_lines = lines.iter().map(|text| Line::parse(Some(kind), text));
+        section.lines.splice(at..at, new_lines);
+    }
+
+    /// Apply `patch`. Returns the number of entries each of its `remove`
+    /// patterns dropped, in order.
+    pub fn apply_patch(&mut self, patch: &Patch) -> Vec<usize> {
+        if let Some(name) = &patch.name {
+            self.set_meta("name", name);
+        }
+        if let Some(desc) = &patch.desc {
+            self.set_meta("desc", desc);
+        }
+        let removed = patch
+            .remove
+            .iter()
+            .map(|(kind, pattern)| self.remove_matching(kind, pattern))
+            .collect();
+        for (kind, lines) in &patch.append {
+            self.append_lines(kind, lines);
+        }
+        removed
+    }
+
+    /// Hostnames the `[MITM]` section adds to decryption
+    pub fn mitm_hostnames(&self) -> Vec<&str> {
+        self.entries(&SectionKind::Mitm)
+            .filter_map(|entry| match entry {
+                Entry::Mitm(setting) if setting.key == "hostname" => Some(setting),
+                _ => None,
+            })
+            .flat_map(|setting| setting.values.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"#!name=Sample
+#!desc=A sample module
+#!arguments=policy:DIRECT,level:4
+# comment before sections
+
+[General]
+force-http-engine-hosts = %APPEND% a.com, b.com
+
+[Rule]
+DOMAIN,ads.com,{{{policy}}}
+AND,((DOMAIN-SUFFIX,a.com), (DOMAIN-KEYWORD,stun)),REJECT,pre-matching
+
+[URL Rewrite]
+^https?:\/\/g\.cn https://www.google.com 307
+
+[Header Rewrite]
+http-request ^https:\/\/a\.com\/x$ header-del if-none-match
+
+[Map Local]
+^https:\/\/a\.com\/ads\? header="content-type: application/json" data-type=text data="{"code":0,"a":[1,2]}"
+
+[Body Rewrite]
+http-response-jq ^https:\/\/a\.com\/conf\? '.data.domains=["wss://x"]'
+
+[Script]
+# a script
+s1 = type=http-response, pattern=^https:\/\/a\.com\/x{2,3}, requires-body=1, script-path=https://e.com/s1.js
+s2 = type=http-request,pattern=^https:\/\/b\.com,argument="{"a":"{{{level}}}","b":1}",script-path=https://e.com/s2.js
+
+[MITM]
+hostname = %APPEND% a.com, *.b.com
+
+[Host]
+a.com = 1.1.1.1
+"#;
+
+    #[test]
+    fn test_roundtrip_is_exact() {
+        assert_eq!(Module::parse(SAMPLE).to_text(), SAMPLE);
+        let crlf = "#!name=x\r\n[MITM]\r\nhostname = a.com";
+        assert_eq!(Module::parse(crlf).to_text(), crlf);
+        assert_eq!(Module::parse("").to_text(), "");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut module = Module::parse(SAMPLE);
+        assert_eq!(module.name(), Some("Sample"));
+        assert_eq!(module.desc(), Some("A sample module"));
+        assert_eq!(module.category(), None);
+        assert_eq!(
+            module.arguments(),
+            vec![
+                Argument {
+                    name: "policy".to_string(),
+                    default: "DIRECT".to_string()
+                },
+                Argument {
+                    name: "level".to_string(),
+                    default: "4".to_string()
+                },
+            ]
+        );
+
+        module.set_meta("name", "Renamed");
+        module.set_meta("category", "Test");
+        let text = module.to_text();
+        assert!(text.starts_with(
+            "#!name=Renamed\n#!desc=A sample module\n#!arguments=policy:DIRECT,level:4\n#!category=Test\n# comment"
+        ));
+    }
+
+    #[test]
+    fn test_argument_defaults() {
+        let mut module = Module::parse(SAMPLE);
+        let overrides = BTreeMap::from([("policy".to_string(), "Proxy".to_string())]);
+        module.set_argument_defaults(&overrides).unwrap();
+        assert_eq!(module.meta("arguments"), Some("policy:Proxy,level:4"));
+        assert!(module.to_text().contains("DOMAIN,ads.com,{{{policy}}}\n"));
+
+        let overrides = BTreeMap::from([("policy".to_string(), "a,b".to_string())]);
+        assert!(matches!(
+            module.set_argument_defaults(&overrides),
+            Err(ArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_render_arguments() {
+        let module = Module::parse(SAMPLE);
+        let overrides = BTreeMap::from([("level".to_string(), "2".to_string())]);
+        let rendered = module.render_arguments(&overrides).unwrap();
+        assert_eq!(rendered.meta("arguments"), None);
+        let text = rendered.to_text();
+        assert!(text.contains("DOMAIN,ads.com,DIRECT\n"));
+        assert!(text.contains(r#"argument="{"a":"2","b":1}""#));
+        assert!(!text.contains("{{{"));
+
+        let overrides = BTreeMap::from([("nope".to_string(), "1".to_string())]);
+        assert_eq!(
+            module.render_arguments(&overrides).unwrap_err().to_string(),
+            "unknown module argument `nope`, expected one of: policy, level"
+        );
+        let plain = Module::parse("#!name=x\n[MITM]\nhostname = a.com\n");
+        assert!(plain
+            .render_arguments(&overrides)
+            .unwrap_err()
+            .to_string()
+            .contains("declares no #!arguments"));
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let mut module = Module::parse(SAMPLE);
+        let patch = Patch {
+            name: Some("Patched".to_string()),
+            desc: None,
+            remove: vec![
+                (SectionKind::Script, Regex::new(r"^s1 =").unwrap()),
+                (SectionKind::Mitm, Regex::new(r"^\*\.b\.com$").unwrap()),
+                (SectionKind::Rule, Regex::new("nothing").unwrap()),
+            ],
+            append: vec![
+                (SectionKind::Rule, vec!["DOMAIN,x.com,REJECT".to_string()]),
+                (
+                    SectionKind::UrlRewrite,
+                    vec!["^http://y\\.com - reject".to_string()],
+                ),
+                (
+                    SectionKind::from_name("Panel"),
+                    vec!["p = title=x".to_string()],
+                ),
+            ],
+        };
+        assert_eq!(module.apply_patch(&patch), [1, 1, 0]);
+
+        assert_eq!(module.name(), Some("Patched"));
+        assert_eq!(module.desc(), Some("A sample module"));
+        let names: Vec<&str> = module.scripts().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["s2"]);
+        assert_eq!(module.mitm_hostnames(), ["a.com"]);
+
+        let text = module.to_text();
+        assert!(text.contains(
+            "AND,((DOMAIN-SUFFIX,a.com), (DOMAIN-KEYWORD,stun)),REJECT,pre-matching\nDOMAIN,x.com,REJECT\n\n[URL Rewrite]"
+        ));
+        assert!(text.contains("307\n^http://y\\.com - reject\n\n[Header Rewrite]"));
+        assert!(text.contains("[MITM]\nhostname = %APPEND% a.com\n"));
+        assert!(text.ends_with("a.com = 1.1.1.1\n\n[Panel]\np = title=x\n"));
+
+        // Removing every value drops the line
+        let all = Regex::new(".").unwrap();
+        assert_eq!(module.remove_matching(&SectionKind::Mitm, &all), 1);
+        assert!(module.mitm_hostnames().is_empty());
+        assert!(!module.to_text().contains("hostname ="));
+    }
+
+    #[test]
+    fn test_typed_entries() {
+        let module = Module::parse(SAMPLE);
+        let kinds: Vec<&str> = module.sections.iter().map(|s| s.kind.name()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "General",
+                "Rule",
+                "URL Rewrite",
+                "Header Rewrite",
+                "Map Local",
+                "Body Rewrite",
+                "Script",
+                "MITM",
+                "Host"
+            ]
+        );
+        let others: Vec<String> = module
+            .sections
+            .iter()
+            .flat_map(Section::entries)
+            .filter(|entry| matches!(entry, Entry::Other(_)))
+            .map(Entry::to_string)
+            .collect();
+        assert_eq!(others, ["a.com = 1.1.1.1"]);
+
+        let rules: Vec<&Entry> = module.entries(&SectionKind::Rule).collect();
+        assert!(matches!(rules[0], Entry::Rule(r) if r.policy.as_deref() == Some("{{{policy}}}")));
+        assert!(matches!(rules[1], Entry::Rule(r) if r.policy.as_deref() == Some("REJECT")));
+
+        let scripts: Vec<&Script> = module.scripts().collect();
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(
+            scripts[0].get("pattern"),
+            Some(r"^https:\/\/a\.com\/x{2,3}")
+        );
+        assert!(scripts[0].requires_body());
+        assert_eq!(
+            scripts[1].get("argument"),
+            Some(r#"{"a":"{{{level}}}","b":1}"#)
+        );
+        assert_eq!(scripts[1].script_path(), Some("https://e.com/s2.js"));
+        assert!(!scripts[1].requires_body());
+
+        let map_local = module.entries(&SectionKind::MapLocal).next().unwrap();
+        let Entry::MapLocal { params, .. } = map_local else {
+            panic!("expected map local, got {:?}", map_local);
+        };
+        let keys: Vec<&str> = params.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(keys, ["header", "data-type", "data"]);
+        assert_eq!(params[2].unquoted(), r#"{"code":0,"a":[1,2]}"#);
+
+        assert_eq!(module.mitm_hostnames(), ["a.com", "*.b.com"]);
+    }
+
+    #[test]
+    fn test_replaced_entries_are_canonical() {
+        let mut module = Module::parse(SAMPLE);
+        for section in &mut module.sections {
+            for line in &mut section.lines {
+                if let Some(Entry::Script(script)) = line.entry() {
+                    let mut script = script.clone();
+                    script.set("script-path", "https://mine.com/x.js");
+                    *line = Line::new(Entry::Script(script));
+                }
+            }
+        }
+        let text = module.to_text();
+        assert!(text.contains(
+            "s1 = type=http-response,pattern=^https:\\/\\/a\\.com\\/x{2,3},requires-body=1,script-path=https://mine.com/x.js\n"
+        ));
+        let reparsed = Module::parse(&text);
+        assert!(reparsed
+            .scripts()
+            .all(|script| script.script_path() == Some("https://mine.com/x.js")));
+    }
+
+    #[test]
+    fn test_repo_modules_roundtrip() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../modules");
+        for category in std::fs::read_dir(&root).unwrap() {
+            for file in std::fs::read_dir(category.unwrap().path()).unwrap() {
+                let path = file.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "sgmodule") {
+                    let text = std::fs::read_to_string(&path).unwrap();
+                    let module = Module::parse(&text);
+                    assert_eq!(module.to_text(), text, "{}", path.display());
+                    assert!(module.name().is_some(), "{}", path.display());
+                }
+            }
+        }
+    }
+}
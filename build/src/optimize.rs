@@ -0,0 +1,229 @@
+//! Rule-set optimizer
+//!
+//! Removes duplicate and redundant rules and merges overlapping IP ranges
+//! while keeping the original rule order.
+
+use std::collections::{HashMap, HashSet};
+
+use ipnet::{Ipv4Net, Ipv6Net};
+
+use crate::rule::{Rule, RuleEntry, RuleOptions};
+
+/// Rules only cover each other when they share a policy and options
+type GroupKey = (Option<String>, RuleOptions);
+
+fn group_key(entry: &RuleEntry) -> GroupKey {
+    (entry.policy.clone(), entry.options)
+}
+
+/// Parent domains of `domain`, longest first: `a.b.com` -> `b.com`, `com`
+fn parent_domains(domain: &str) -> impl Iterator<Item = &str> {
+    domain
+        .match_indices('.')
+        .map(move |(i, _)| &domain[i + 1..])
+        .filter(|parent| !parent.is_empty())
+}
+
+/// Whether one of `suffixes` (lowercase) is a parent domain of `domain`
+fn under_suffix(domain: &str, suffixes: &HashSet<String>) -> bool {
+    parent_domains(domain).any(|parent| suffixes.contains(parent))
+}
+
+/// Remove exact duplicates, keeping the first occurrence of each rule
+pub fn dedupe(entries: Vec<RuleEntry>) -> Vec<RuleEntry> {
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.clone()))
+        .collect()
+}
+
+/// True if `a` and `b` are the same rule, ignoring the case of domains
+fn same_rule(a: &Rule, b: &Rule) -> bool {
+    match (a, b) {
+        (Rule::Domain(a), Rule::Domain(b))
+        | (Rule::DomainSuffix(a), Rule::DomainSuffix(b))
+        | (Rule::DomainKeyword(a), Rule::DomainKeyword(b)) => a.eq_ignore_ascii_case(b),
+        _ => a == b,
+    }
+}
+
+/// Drop every entry an `excluded` rule matches, whatever its policy: the
+/// same rule, or a `DOMAIN` or `DOMAIN-SUFFIX` under an excluded
+/// `DOMAIN-SUFFIX`, using the same coverage rule as [`optimize`].
+pub fn exclude(entries: Vec<RuleEntry>, excluded: &[RuleEntry]) -> Vec<RuleEntry> {
+    let suffixes: HashSet<String> = excluded
+        .iter()
+        .filter_map(|x| match &x.rule {
+            Rule::DomainSuffix(suffix) => Some(suffix.to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let covered = match &entry.rule {
+                Rule::Domain(domain) | Rule::DomainSuffix(domain) => {
+                    let domain = domain.to_ascii_lowercase();
+                    suffixes.contains(&domain) || under_suffix(&domain, &suffixes)
+                }
+                _ => false,
+            };
+            !covered && !excluded.iter().any(|x| same_rule(&x.rule, &entry.rule))
+        })
+        .collect()
+}
+
+/// Optimize a list of rules:
+///
+/// * exact duplicates are removed
+/// * `DOMAIN` entries matched by a `DOMAIN-SUFFIX` in the same set are removed
+/// * `DOMAIN-SUFFIX` entries shadowed by a shorter suffix are removed
+/// * `IP-CIDR` and `IP-CIDR6` ranges that are adjacent or nested are merged
+pub fn optimize(entries: Vec<RuleEntry>) -> Vec<RuleEntry> {
+    let entries = dedupe(entries);
+
+    let mut suffixes: HashMap<GroupKey, HashSet<String>> = HashMap::new();
+    for entry in &entries {
+        if let Rule::DomainSuffix(suffix) = &entry.rule {
+            suffixes
+                .entry(group_key(entry))
+                .or_default()
+                .insert(suffix.to_ascii_lowercase());
+        }
+    }
+
+    let covered = |entry: &RuleEntry| {
+        let Some(set) = suffixes.get(&group_key(entry)) else {
+            return false;
+        };
+        match &entry.rule {
+            Rule::Domain(domain) => {
+                let domain = domain.to_ascii_lowercase();
+                set.contains(&domain) || under_suffix(&domain, set)
+            }
+            Rule::DomainSuffix(suffix) => under_suffix(&suffix.to_ascii_lowercase(), set),
+            _ => false,
+        }
+    };
+
+    // Collect IP ranges per group so they can be merged in place
+    let mut v4: HashMap<GroupKey, Vec<Ipv4Net>> = HashMap::new();
+    let mut v6: HashMap<GroupKey, Vec<Ipv6Net>> = HashMap::new();
+    for entry in &entries {
+        match &entry.rule {
+            Rule::IpCidr(net) => v4.entry(group_key(entry)).or_default().push(*net),
+            Rule::IpCidr6(net) => v6.entry(group_key(entry)).or_default().push(*net),
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::with_capacity(entries.len());
+    let mut emitted_v4 = HashSet::new();
+    let mut emitted_v6 = HashSet::new();
+
+    for entry in &entries {
+        match &entry.rule {
+            Rule::IpCidr(_) => {
+                let key = group_key(entry);
+                if emitted_v4.insert(key.clone()) {
+                    let nets = &v4[&key];
+                    let merged = Ipv4Net::aggregate(nets);
+                    if merged.len() < nets.len() {
+                        out.extend(merged.into_iter().map(|net| RuleEntry {
+                            rule: Rule::IpCidr(net),
+                            ..entry.clone()
+                        }));
+                    } else {
+                        out.extend(nets.iter().map(|net| RuleEntry {
+                            rule: Rule::IpCidr(*net),
+                            ..entry.clone()
+                        }));
+                    }
+                }
+            }
+            Rule::IpCidr6(_) => {
+                let key = group_key(entry);
+                if emitted_v6.insert(key.clone()) {
+                    let nets = &v6[&key];
+                    let merged = Ipv6Net::aggregate(nets);
+                    if merged.len() < nets.len() {
+                        out.extend(merged.into_iter().map(|net| RuleEntry {
+                            rule: Rule::IpCidr6(net),
+                            ..entry.clone()
+                        }));
+                    } else {
+                        out.extend(nets.iter().map(|net| RuleEntry {
+                            rule: Rule::IpCidr6(*net),
+                            ..entry.clone()
+                        }));
+                    }
+                }
+            }
+            _ if covered(entry) => {}
+            _ => out.push(entry.clone()),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::{format_rules, RuleSet};
+
+    fn optimized(text: &str) -> String {
+        format_rules(&optimize(RuleSet::parse(text).entries))
+    }
+
+    #[test]
+    fn test_removes_duplicates_and_covered_domains() {
+        let text = "DOMAIN-SUFFIX,a.com\nDOMAIN,a.com\nDOMAIN,x.A.com\nDOMAIN-SUFFIX,b.a.com\nDOMAIN-SUFFIX,c.com\nDOMAIN-SUFFIX,c.com\nDOMAIN,bc.com\nDOMAIN-KEYWORD,a\n";
+        assert_eq!(
+            optimized(text),
+            "DOMAIN-SUFFIX,a.com\nDOMAIN-SUFFIX,c.com\nDOMAIN,bc.com\nDOMAIN-KEYWORD,a\n"
+        );
+    }
+
+    #[test]
+    fn test_coverage_respects_policy_and_options() {
+        let text = "DOMAIN-SUFFIX,a.com,reject\nDOMAIN,x.a.com\nDOMAIN-SUFFIX,b.com,extended-matching\nDOMAIN,b.com\n";
+        assert_eq!(optimized(text), text);
+    }
+
+    #[test]
+    fn test_exclude_covers_subdomains() {
+        let excluded = RuleSet::parse("DOMAIN-SUFFIX,x.ai\nDOMAIN-KEYWORD,grok\n").entries;
+        let text = "DOMAIN-SUFFIX,x.ai\nDOMAIN,X.ai\nDOMAIN,api.x.ai,DIRECT\nDOMAIN-SUFFIX,cdn.x.ai\nDOMAIN,box.ai\nDOMAIN-KEYWORD,grok\nDOMAIN-KEYWORD,grok2\n";
+        assert_eq!(
+            format_rules(&exclude(RuleSet::parse(text).entries, &excluded)),
+            "DOMAIN,box.ai\nDOMAIN-KEYWORD,grok2\n"
+        );
+    }
+
+    #[test]
+    fn test_exclude_ignores_case() {
+        let excluded = RuleSet::parse("DOMAIN,Foo.com\nDOMAIN-KEYWORD,Grok\n").entries;
+        let text = "DOMAIN,foo.com\nDOMAIN,FOO.COM,DIRECT\nDOMAIN,a.foo.com\nDOMAIN-KEYWORD,grok\n";
+        assert_eq!(
+            format_rules(&exclude(RuleSet::parse(text).entries, &excluded)),
+            "DOMAIN,a.foo.com\n"
+        );
+    }
+
+    #[test]
+    fn test_merges_ip_ranges() {
+        let text = "IP-CIDR,10.0.0.0/25,no-resolve\nDOMAIN,a.com\nIP-CIDR,10.0.0.128/25,no-resolve\nIP-CIDR,10.0.0.5/32,no-resolve\nIP-CIDR6,2001:db8::/33\nIP-CIDR6,2001:db8:8000::/33\n";
+        assert_eq!(
+            optimized(text),
+            "IP-CIDR,10.0.0.0/24,no-resolve\nDOMAIN,a.com\nIP-CIDR6,2001:db8::/32\n"
+        );
+    }
+
+    #[test]
+    fn test_unmergeable_ranges_keep_order() {
+        let text = "IP-CIDR,10.0.2.0/24\nIP-CIDR,10.0.0.0/24\n";
+        assert_eq!(optimized(text), text);
+    }
+}
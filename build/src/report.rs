@@ -0,0 +1,143 @@
+//! Machine-readable sync report
+//!
+//! A [`SyncReport`] collects the outcome of every source a run selected, with
+//! run totals, and serializes to JSON for dashboards and bots that should not
+//! have to scrape the colored log output.
+
+use serde::Serialize;
+
+/// Outcome of one source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// At least one output was (or, in a dry run, would be) rewritten
+    Updated,
+    Unchanged,
+    Failed,
+}
+
+/// What happened to one source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceReport {
+    pub kind: String,
+    pub name: String,
+    pub status: Status,
+    /// URLs and local paths the source reads from
+    pub upstreams: Vec<String>,
+    /// Rules in the file before the sync, for rule sources with an existing file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_entries: Option<usize>,
+    /// Rules the sync produced, for rule sources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_entries: Option<usize>,
+    /// Bytes downloaded for the source
+    pub bytes: u64,
+    /// Time spent on the source, not counting downloads that ran in the
+    /// shared parallel batch before it
+    pub duration_ms: u64,
+    /// Short failure label, e.g. `timeout` or `HTTP 404`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Counts over every source in a report
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub sources: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    pub bytes: u64,
+}
+
+/// Report of a whole run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncReport {
+    /// Version of the tool that produced the report
+    pub version: String,
+    /// RFC 3339 start time of the run
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub dry_run: bool,
+    pub totals: Totals,
+    pub sources: Vec<SourceReport>,
+}
+
+impl SyncReport {
+    /// Start an empty report for a run that began now
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            duration_ms: 0,
+            dry_run,
+            totals: Totals::default(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// Add a source's outcome and count it in the totals
+    pub fn push(&mut self, source: SourceReport) {
+        self.totals.sources += 1;
+        self.totals.bytes += source.bytes;
+        match source.status {
+            Status::Updated => self.totals.updated += 1,
+            Status::Unchanged => self.totals.unchanged += 1,
+            Status::Failed => self.totals.failed += 1,
+        }
+        self.sources.push(source);
+    }
+
+    /// Pretty-printed JSON with a trailing newline
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("reports always serialize");
+        json.push('\n');
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, status: Status, bytes: u64) -> SourceReport {
+        SourceReport {
+            kind: "rule".to_string(),
+            name: name.to_string(),
+            status,
+            upstreams: vec![format!("https://e.com/{}.conf", name)],
+            old_entries: None,
+            new_entries: Some(2),
+            bytes,
+            duration_ms: 5,
+            error_kind: (status == Status::Failed).then(|| "HTTP 404".to_string()),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_totals_and_json() {
+        let mut report = SyncReport::new(false);
+        report.push(source("a", Status::Updated, 10));
+        report.push(source("b", Status::Unchanged, 0));
+        report.push(source("c", Status::Failed, 0));
+        assert_eq!(
+            report.totals,
+            Totals {
+                sources: 3,
+                updated: 1,
+                unchanged: 1,
+                failed: 1,
+                bytes: 10,
+            }
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["sources"][0]["status"], "updated");
+        assert_eq!(json["sources"][2]["error_kind"], "HTTP 404");
+        assert!(json["sources"][0].get("old_entries").is_none());
+        assert_eq!(json["totals"]["failed"], 1);
+    }
+}
@@ -0,0 +1,611 @@
+//! Surge rule parser
+//!
+//! Turns rule-set lines into typed [`Rule`]s, reports lines that cannot be
+//! parsed as [`Diagnostic`]s, and writes rules back in canonical form.
+
+use std::fmt;
+
+use ipnet::{Ipv4Net, Ipv6Net};
+
+/// A single Surge rule matcher
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Rule {
+    Domain(String),
+    DomainSuffix(String),
+    DomainKeyword(String),
+    DomainWildcard(String),
+    IpCidr(Ipv4Net),
+    IpCidr6(Ipv6Net),
+    IpAsn(u32),
+    Geoip(String),
+    UserAgent(String),
+    UrlRegex(String),
+    ProcessName(String),
+    DestPort(String),
+    And(Vec<RuleEntry>),
+    Or(Vec<RuleEntry>),
+    Not(Box<RuleEntry>),
+    /// A rule type Surge accepts that is kept as an opaque value
+    Other {
+        rule_type: &'static str,
+        value: String,
+    },
+}
+
+/// Surge rule types without a dedicated variant, kept as [`Rule::Other`]
+const OTHER_SURGE_TYPES: &[&str] = &[
+    "SRC-IP",
+    "SRC-PORT",
+    "IN-PORT",
+    "PROTOCOL",
+    "SUBNET",
+    "DEVICE-NAME",
+    "HOSTNAME-TYPE",
+    "CELLULAR-RADIO",
+    "CELLULAR-CARRIER",
+    "SCRIPT",
+];
+
+/// Rule types that only Clash-family clients understand
+const CLASH_ONLY_TYPES: &[&str] = &[
+    "DST-PORT",
+    "SRC-IP-CIDR",
+    "SRC-IP-CIDR6",
+    "SRC-IP-ASN",
+    "SRC-GEOIP",
+    "GEOSITE",
+    "DOMAIN-REGEX",
+    "IP-SUFFIX",
+    "PROCESS-PATH",
+    "PROCESS-NAME-REGEX",
+    "PROCESS-PATH-REGEX",
+    "NETWORK",
+    "UID",
+    "IN-TYPE",
+    "IN-USER",
+    "IN-NAME",
+    "SUB-RULE",
+    "MATCH",
+];
+
+/// Trailing flags a rule may carry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RuleOptions {
+    pub no_resolve: bool,
+    pub extended_matching: bool,
+    pub pre_matching: bool,
+}
+
+/// A parsed rule line: the matcher, an optional policy and its options
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RuleEntry {
+    pub rule: Rule,
+    pub policy: Option<String>,
+    pub options: RuleOptions,
+}
+
+/// Why a line could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The rule type is not one this parser understands
+    UnknownType(String),
+    /// The rule type exists in other clients but Surge refuses it
+    UnsupportedType(String),
+    /// The rule type was given without a value
+    MissingValue(String),
+    /// The value does not fit the rule type
+    InvalidValue {
+        rule_type: String,
+        value: String,
+        reason: String,
+    },
+    /// A trailing field is neither a policy nor a known option
+    UnknownOption(String),
+    /// Unbalanced parentheses or quotes
+    Malformed(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownType(t) => write!(f, "unknown rule type `{}`", t),
+            ParseError::UnsupportedType(t) => {
+                write!(
+                    f,
+                    "rule type `{}` is Clash-only and not supported by Surge",
+                    t
+                )
+            }
+            ParseError::MissingValue(t) => write!(f, "{} rule has no value", t),
+            ParseError::InvalidValue {
+                rule_type,
+                value,
+                reason,
+            } => write!(f, "invalid {} value `{}`: {}", rule_type, value, reason),
+            ParseError::UnknownOption(o) => write!(f, "unknown rule option `{}`", o),
+            ParseError::Malformed(reason) => write!(f, "malformed rule: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A line that could not be parsed, with its 1-based line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub text: String,
+    pub error: ParseError,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {} (`{}`)", self.line, self.error, self.text)
+    }
+}
+
+/// Parsed content of a rule-set file
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    pub entries: Vec<RuleEntry>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Rule {
+    /// The Surge keyword for this rule type
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Rule::Domain(_) => "DOMAIN",
+            Rule::DomainSuffix(_) => "DOMAIN-SUFFIX",
+            Rule::DomainKeyword(_) => "DOMAIN-KEYWORD",
+            Rule::DomainWildcard(_) => "DOMAIN-WILDCARD",
+            Rule::IpCidr(_) => "IP-CIDR",
+            Rule::IpCidr6(_) => "IP-CIDR6",
+            Rule::IpAsn(_) => "IP-ASN",
+            Rule::Geoip(_) => "GEOIP",
+            Rule::UserAgent(_) => "USER-AGENT",
+            Rule::UrlRegex(_) => "URL-REGEX",
+            Rule::ProcessName(_) => "PROCESS-NAME",
+            Rule::DestPort(_) => "DEST-PORT",
+            Rule::And(_) => "AND",
+            Rule::Or(_) => "OR",
+            Rule::Not(_) => "NOT",
+            Rule::Other { rule_type, .. } => rule_type,
+        }
+    }
+
+    /// Parse the value of a non-logical rule
+    fn from_parts(rule_type: &str, value: &str) -> Result<Self, ParseError> {
+        let invalid = |reason: &str| ParseError::InvalidValue {
+            rule_type: rule_type.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if value.is_empty() {
+            return Err(ParseError::MissingValue(rule_type.to_string()));
+        }
+
+        let plain = || {
+            if value.chars().any(char::is_whitespace) {
+                Err(invalid("contains whitespace"))
+            } else {
+                Ok(value.to_string())
+            }
+        };
+
+        Ok(match rule_type {
+            "DOMAIN" => Rule::Domain(plain()?),
+            "DOMAIN-SUFFIX" => Rule::DomainSuffix(plain()?),
+            "DOMAIN-KEYWORD" => Rule::DomainKeyword(plain()?),
+            "DOMAIN-WILDCARD" => Rule::DomainWildcard(plain()?),
+            "IP-CIDR" => Rule::IpCidr(value.parse().map_err(|_| invalid("not an IPv4 CIDR"))?),
+            "IP-CIDR6" => Rule::IpCidr6(value.parse().map_err(|_| invalid("not an IPv6 CIDR"))?),
+            "IP-ASN" => Rule::IpAsn(value.parse().map_err(|_| invalid("not an AS number"))?),
+            "GEOIP" => Rule::Geoip(plain()?),
+            "USER-AGENT" => Rule::UserAgent(value.to_string()),
+            "URL-REGEX" => {
+                if let Err(reason) = check_url_regex(value) {
+                    return Err(invalid(&reason));
+                }
+                Rule::UrlRegex(value.to_string())
+            }
+            "PROCESS-NAME" => Rule::ProcessName(value.to_string()),
+            "DEST-PORT" => {
+                if !is_port_spec(value) {
+                    return Err(invalid("not a port or port range"));
+                }
+                Rule::DestPort(value.to_string())
+            }
+            other => {
+                if let Some(rule_type) = OTHER_SURGE_TYPES.iter().find(|t| **t == other) {
+                    return Ok(Rule::Other {
+                        rule_type,
+                        value: value.to_string(),
+                    });
+                }
+                if CLASH_ONLY_TYPES.contains(&other) {
+                    return Err(ParseError::UnsupportedType(other.to_string()));
+                }
+                return Err(ParseError::UnknownType(other.to_string()));
+            }
+        })
+    }
+
+    /// The textual value of a non-logical rule
+    fn value(&self) -> String {
+        match self {
+            Rule::Domain(v)
+            | Rule::DomainSuffix(v)
+            | Rule::DomainKeyword(v)
+            | Rule::DomainWildcard(v)
+            | Rule::Geoip(v)
+            | Rule::UserAgent(v)
+            | Rule::UrlRegex(v)
+            | Rule::ProcessName(v)
+            | Rule::DestPort(v)
+            | Rule::Other { value: v, .. } => v.clone(),
+            Rule::IpCidr(net) => net.to_string(),
+            Rule::IpCidr6(net) => net.to_string(),
+            Rule::IpAsn(asn) => asn.to_string(),
+            Rule::And(entries) | Rule::Or(entries) => format_group(entries),
+            Rule::Not(entry) => format_group(std::slice::from_ref(entry.as_ref())),
+        }
+    }
+}
+
+/// `443` or `8000-8080`
+fn is_port_spec(value: &str) -> bool {
+    let port = |s: &str| s.parse::<u16>().ok();
+    match value.split_once('-') {
+        Some((lo, hi)) => matches!((port(lo), port(hi)), (Some(lo), Some(hi)) if lo <= hi),
+        None => port(value).is_some(),
+    }
+}
+
+fn format_group(entries: &[RuleEntry]) -> String {
+    let inner: Vec<String> = entries.iter().map(|e| format!("({})", e)).collect();
+    format!("({})", inner.join(","))
+}
+
+/// Split on commas outside quoted strings and, when `nested` is set,
+/// outside parenthesized groups. Fields are trimmed.
+fn split_fields(text: &str, nested: bool) -> Result<Vec<&str>, ParseError> {
+    let mut fields = Vec::new();
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if nested && !quoted => depth += 1,
+            ')' if nested && !quoted => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| ParseError::Malformed("unexpected `)`".to_string()))?;
+            }
+            ',' if !quoted && depth == 0 => {
+                fields.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if quoted {
+        return Err(ParseError::Malformed("unterminated quote".to_string()));
+    }
+    if depth != 0 {
+        return Err(ParseError::Malformed("unbalanced parentheses".to_string()));
+    }
+    fields.push(text[start..].trim());
+    Ok(fields)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Check that a URL-REGEX pattern compiles. Surge uses ICU, which unlike
+/// the `regex` crate allows lookarounds and backreferences, so a pattern
+/// `regex` refuses only for those is compiled with the backtracking
+/// `fancy-regex` instead; every pattern must also compile there.
+fn check_url_regex(pattern: &str) -> Result<(), String> {
+    use regex_syntax::ast::ErrorKind;
+
+    if let Err(e) = regex::Regex::new(pattern) {
+        let unsupported = regex_syntax::ast::parse::Parser::new()
+            .parse(pattern)
+            .is_err_and(|e| {
+                matches!(
+                    e.kind(),
+                    ErrorKind::UnsupportedLookAround | ErrorKind::UnsupportedBackreference
+                )
+            });
+        if !unsupported {
+            // The last line of the message names the problem
+            let message = e.to_string();
+            let reason = message.lines().last().unwrap_or_default();
+            return Err(reason.trim_start_matches("error: ").to_string());
+        }
+    }
+    fancy_regex::Regex::new(pattern)
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Parse the `((A,x),(B,y))` operand of a logical rule
+fn parse_group(rule_type: &str, value: &str) -> Result<Vec<RuleEntry>, ParseError> {
+    let malformed =
+        || ParseError::Malformed(format!("{} expects a ((rule),(rule)) group", rule_type));
+    let inner = value
+        .strip_prefix('(')
+        .and_then(|v| v.strip_suffix(')'))
+        .ok_or_else(malformed)?;
+
+    split_fields(inner, true)?
+        .into_iter()
+        .map(|part| {
+            let sub = part
+                .strip_prefix('(')
+                .and_then(|v| v.strip_suffix(')'))
+                .ok_or_else(malformed)?;
+            sub.parse()
+        })
+        .collect()
+}
+
+impl std::str::FromStr for RuleEntry {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let rule_type = line.split(',').next().unwrap_or_default().trim();
+        let nested = matches!(
+            rule_type.to_ascii_uppercase().as_str(),
+            "AND" | "OR" | "NOT"
+        );
+        let fields = split_fields(line, nested)?;
+        let rule_type = fields[0].to_ascii_uppercase();
+        let value = fields.get(1).map(|v| unquote(v)).unwrap_or_default();
+
+        let rule = match rule_type.as_str() {
+            "AND" | "OR" | "NOT" if value.is_empty() => {
+                return Err(ParseError::MissingValue(rule_type));
+            }
+            "AND" => Rule::And(parse_group(&rule_type, value)?),
+            "OR" => Rule::Or(parse_group(&rule_type, value)?),
+            "NOT" => {
+                let mut group = parse_group(&rule_type, value)?;
+                if group.len() != 1 {
+                    return Err(ParseError::Malformed(
+                        "NOT expects exactly one rule".to_string(),
+                    ));
+                }
+                Rule::Not(Box::new(group.remove(0)))
+            }
+            _ => Rule::from_parts(&rule_type, value)?,
+        };
+
+        let mut policy = None;
+        let mut options = RuleOptions::default();
+        for (i, field) in fields.iter().skip(2).enumerate() {
+            match *field {
+                "no-resolve" => options.no_resolve = true,
+                "extended-matching" => options.extended_matching = true,
+                "pre-matching" => options.pre_matching = true,
+                other if i == 0 && !other.is_empty() => policy = Some(other.to_string()),
+                other => return Err(ParseError::UnknownOption(other.to_string())),
+            }
+        }
+
+        Ok(RuleEntry {
+            rule,
+            policy,
+            options,
+        })
+    }
+}
+
+impl fmt::Display for RuleEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.rule.value();
+        if value.contains(',') && !matches!(self.rule, Rule::And(_) | Rule::Or(_) | Rule::Not(_)) {
+            write!(f, "{},\"{}\"", self.rule.type_name(), value)?;
+        } else {
+            write!(f, "{},{}", self.rule.type_name(), value)?;
+        }
+        if let Some(policy) = &self.policy {
+            write!(f, ",{}", policy)?;
+        }
+        if self.options.no_resolve {
+            f.write_str(",no-resolve")?;
+        }
+        if self.options.extended_matching {
+            f.write_str(",extended-matching")?;
+        }
+        if self.options.pre_matching {
+            f.write_str(",pre-matching")?;
+        }
+        Ok(())
+    }
+}
+
+/// True for blank lines and `#` or `//` comments
+pub fn is_comment_or_blank(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//")
+}
+
+/// Canonical text for a list of rules, one rule per line
+pub fn format_rules(entries: &[RuleEntry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&entry.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+impl RuleSet {
+    /// Parse every rule line, collecting diagnostics for the ones that fail
+    pub fn parse(text: &str) -> Self {
+        let mut set = RuleSet::default();
+        for (i, line) in text.lines().enumerate() {
+            if is_comment_or_blank(line) {
+                continue;
+            }
+            match line.parse() {
+                Ok(entry) => set.entries.push(entry),
+                Err(error) => set.diagnostics.push(Diagnostic {
+                    line: i + 1,
+                    text: line.trim().to_string(),
+                    error,
+                }),
+            }
+        }
+        set
+    }
+
+    /// Canonical text, one rule per line
+    pub fn to_text(&self) -> String {
+        format_rules(&self.entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(line: &str) -> String {
+        line.parse::<RuleEntry>().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_simple_rules() {
+        let entry: RuleEntry = "DOMAIN-SUFFIX,claude.ai".parse().unwrap();
+        assert_eq!(entry.rule, Rule::DomainSuffix("claude.ai".to_string()));
+        assert_eq!(entry.policy, None);
+
+        let entry: RuleEntry = "IP-CIDR,91.108.4.0/22,no-resolve".parse().unwrap();
+        assert_eq!(entry.rule, Rule::IpCidr("91.108.4.0/22".parse().unwrap()));
+        assert!(entry.options.no_resolve);
+
+        let entry: RuleEntry = "DOMAIN-SUFFIX,go.mnaspm.com,reject".parse().unwrap();
+        assert_eq!(entry.policy.as_deref(), Some("reject"));
+
+        let entry: RuleEntry = "ip-asn,44907".parse().unwrap();
+        assert_eq!(entry.rule, Rule::IpAsn(44907));
+    }
+
+    #[test]
+    fn test_roundtrip_canonical() {
+        for line in [
+            "DOMAIN,ai.google.dev",
+            "DOMAIN-WILDCARD,*.example.?om",
+            "IP-CIDR6,2001:b28:f23d::/48,no-resolve",
+            "USER-AGENT,*bili*",
+            "PROCESS-NAME,Telegram",
+            "DEST-PORT,8000-8080",
+            "GEOIP,CN,extended-matching",
+            "URL-REGEX,https://www\\.google\\.com/.*continue=.+",
+            "URL-REGEX,\"^https?://a{1,3}\\.com\"",
+            "URL-REGEX,^https?://(www\\.)?x\\.com/\\(",
+        ] {
+            assert_eq!(roundtrip(line), line);
+        }
+        assert_eq!(roundtrip(" domain-suffix , x.com "), "DOMAIN-SUFFIX,x.com");
+    }
+
+    #[test]
+    fn test_logical_rules_nest() {
+        let line = "OR,((IP-ASN,44907,no-resolve),(IP-ASN,59930,no-resolve))";
+        let entry: RuleEntry = line.parse().unwrap();
+        match &entry.rule {
+            Rule::Or(entries) => {
+                assert_eq!(entries.len(), 2);
+                assert!(entries[0].options.no_resolve);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(entry.to_string(), line);
+
+        let line = "AND,((NOT,((DOMAIN-SUFFIX,a.com))), (DEST-PORT,443)),REJECT";
+        let entry: RuleEntry = line.parse().unwrap();
+        assert_eq!(
+            entry.to_string(),
+            "AND,((NOT,((DOMAIN-SUFFIX,a.com))),(DEST-PORT,443)),REJECT"
+        );
+    }
+
+    #[test]
+    fn test_malformed_lines_become_diagnostics() {
+        let text = "# header\nDOMAIN,a.com\nDST-PORT,443\nIP-CIDR,1.2.3.999/24\nDOMAIN-SUFFIX\nAND,((DOMAIN,a.com)\nDOMAIN,b.com,DIRECT,bogus\n";
+        let set = RuleSet::parse(text);
+        assert_eq!(set.entries.len(), 1);
+        let lines: Vec<usize> = set.diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+        assert_eq!(
+            set.diagnostics[0].error,
+            ParseError::UnsupportedType("DST-PORT".to_string())
+        );
+        assert!(matches!(
+            set.diagnostics[1].error,
+            ParseError::InvalidValue { .. }
+        ));
+        assert!(matches!(
+            set.diagnostics[4].error,
+            ParseError::UnknownOption(_)
+        ));
+    }
+
+    #[test]
+    fn test_surge_support_checks() {
+        let entry: RuleEntry = "SRC-IP,192.168.1.2".parse().unwrap();
+        assert_eq!(entry.rule.type_name(), "SRC-IP");
+        assert_eq!(entry.to_string(), "SRC-IP,192.168.1.2");
+
+        assert_eq!(
+            "GEOSITE,google".parse::<RuleEntry>().unwrap_err(),
+            ParseError::UnsupportedType("GEOSITE".to_string())
+        );
+        assert_eq!(
+            "FOO,bar".parse::<RuleEntry>().unwrap_err(),
+            ParseError::UnknownType("FOO".to_string())
+        );
+        assert!(matches!(
+            "URL-REGEX,^https://(a\\.com".parse::<RuleEntry>(),
+            Err(ParseError::InvalidValue { .. })
+        ));
+        for line in [
+            "URL-REGEX,*foo",
+            "URL-REGEX,a{2,1}",
+            "URL-REGEX,\"a{2,1}\"",
+            "URL-REGEX,(?=x)a**",
+            "URL-REGEX,a**",
+            "URL-REGEX,\\",
+        ] {
+            assert!(
+                matches!(
+                    line.parse::<RuleEntry>(),
+                    Err(ParseError::InvalidValue { .. })
+                ),
+                "{}",
+                line
+            );
+        }
+        // ICU syntax the `regex` crate lacks
+        for line in [
+            "URL-REGEX,^https?://(?!www\\.)[^/]+\\.example\\.com/",
+            "URL-REGEX,^https?://(?<=a)b\\.com/(x)\\1",
+            "URL-REGEX,^https?://[(]x\\)\\.com",
+        ] {
+            assert!(line.parse::<RuleEntry>().is_ok(), "{}", line);
+        }
+        assert!("IP-CIDR6,2001:db8::/129".parse::<RuleEntry>().is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Vendored module scripts
+//!
+//! Names the local copies of a module's remote scripts and maps the script
+//! paths of a vendored module back to the upstream URLs the copies were
+//! downloaded from, so audits compare upstream against upstream.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::module::{Entry, Line, Module};
+
+/// Local file name for a script URL: its last path segment, made unique
+/// among the file names already `taken` by the module's other scripts
+pub fn script_file_name(url: &str, taken: &HashSet<String>) -> String {
+    let base: String = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let base = match base.trim_matches('.') {
+        "" => "script.js".to_string(),
+        trimmed => trimmed.to_string(),
+    };
+    let (stem, extension) = match base.rsplit_once('.') {
+        Some((stem, extension)) => (stem.to_string(), format!(".{}", extension)),
+        None => (base.clone(), String::new()),
+    };
+
+    let mut name = base;
+    let mut n = 2;
+    while taken.contains(&name) {
+        name = format!("{}-{}{}", stem, n, extension);
+        n += 1;
+    }
+    name
+}
+
+/// Raw URL of the vendored copy `file` of a script of module `name`
+pub fn vendored_url(base_url: &str, name: &str, file: &str) -> String {
+    format!("{}/modules/scripts/{}/{}", base_url, name, file)
+}
+
+/// Upstream URL named in the header of a vendored script
+pub fn upstream_url(vendored: &str) -> Option<&str> {
+    vendored
+        .lines()
+        .find_map(|line| line.strip_prefix("# Upstream: "))
+        .map(str::trim)
+}
+
+/// Point script paths of `module` served from `base_url` back at the
+/// upstream URLs of the vendored copies under `modules_dir`. Paths without
+/// a readable copy are left alone.
+pub fn restore_upstream_paths(module: &mut Module, base_url: &str, modules_dir: &Path) {
+    let prefix = format!("{}/modules/", base_url);
+    for section in &mut module.sections {
+        for line in &mut section.lines {
+            let Some(Entry::Script(script)) = line.entry() else {
+                continue;
+            };
+            let Some(vendored) = script
+                .script_path()
+                .and_then(|path| path.strip_prefix(&prefix))
+                .and_then(|relative| fs::read_to_string(modules_dir.join(relative)).ok())
+            else {
+                continue;
+            };
+            let Some(upstream) = upstream_url(&vendored) else {
+                continue;
+            };
+            let mut script = script.clone();
+            script.set("script-path", upstream);
+            *line = Line::new(Entry::Script(script));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_file_name() {
+        let mut taken = HashSet::new();
+        let url = "https://e.com/u/r/main/js/ads.js?raw=true#x";
+        assert_eq!(script_file_name(url, &taken), "ads.js");
+        taken.insert("ads.js".to_string());
+        assert_eq!(script_file_name(url, &taken), "ads-2.js");
+        taken.insert("ads-2.js".to_string());
+        assert_eq!(script_file_name(url, &taken), "ads-3.js");
+
+        assert_eq!(
+            script_file_name("https://e.com/a%20b(1).js", &taken),
+            "a_20b_1_.js"
+        );
+        assert_eq!(script_file_name("https://e.com/", &taken), "script.js");
+        assert_eq!(script_file_name("https://e.com/run", &taken), "run");
+    }
+
+    #[test]
+    fn test_restore_upstream_paths() {
+        let base = "https://raw.githubusercontent.com/u/r/main";
+        let dir = std::env::temp_dir().join(format!("surge-sync-vendor-{}", std::process::id()));
+        fs::create_dir_all(dir.join("scripts/test")).unwrap();
+        fs::write(
+            dir.join("scripts/test/a.js"),
+            "/*\n# test/a.js\n# Upstream: https://e.com/a.js\n*/\nconsole.log(1);\n",
+        )
+        .unwrap();
+
+        let text = format!(
+            "[Script]\na = type=cron,script-path={}\nb = type=cron,script-path={}\nc = type=cron,script-path=c.js\n",
+            vendored_url(base, "test", "a.js"),
+            vendored_url(base, "test", "missing.js"),
+        );
+        let mut module = Module::parse(&text);
+        restore_upstream_paths(&mut module, base, &dir);
+        let paths: Vec<&str> = module
+            .scripts()
+            .filter_map(|script| script.script_path())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "https://e.com/a.js",
+                "https://raw.githubusercontent.com/u/r/main/modules/scripts/test/missing.js",
+                "c.js",
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
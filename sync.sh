@@ -1,6 +1,6 @@
 #!/bin/bash
 # Surge Resources Sync Script
-# Usage: ./sync.sh
+# Usage: ./sync.sh [surge-sync flags]
 
 set -e
 
@@ -43,28 +43,11 @@ build_tools() {
     log_success "Finished" "building sync tools"
 }
 
-# Run sync_icons
-sync_icons() {
-    log_status "Running" "sync_icons..."
-    ./build/target/release/sync_icons
-}
-
-# Run sync_rules
-sync_rules() {
-    log_status "Running" "sync_rules..."
-    ./build/target/release/sync_rules
-}
-
-# Run sync_modules
-sync_modules() {
-    log_status "Running" "sync_modules..."
-    ./build/target/release/sync_modules
-}
-
-# Run sync_geoip
-sync_geoip() {
-    log_status "Running" "sync_geoip..."
-    ./build/target/release/sync_geoip
+# Run every sync (icons, rules, modules, geoip); extra arguments are
+# forwarded, e.g. ./sync.sh --only ai --dry-run
+run_sync() {
+    log_status "Running" "surge-sync all..."
+    ./build/target/release/surge-sync all "$@"
 }
 
 # Main function
@@ -75,10 +58,7 @@ main() {
 
     check_rust
     build_tools
-    sync_icons
-    sync_rules
-    sync_modules
-    sync_geoip
+    run_sync "$@"
 
     echo ""
     log_success "Done!" "All resources synced successfully"